@@ -0,0 +1,36 @@
+#![no_main]
+//! Feed arbitrary bytes to every parser of key files, public keys, ciphers and messages: they must never panic.
+//! Run with `cargo fuzz run keyfiles`.
+use libfuzzer_sys::fuzz_target;
+use pqx::key::*;
+use pqx::*;
+
+fuzz_target!(|data: &[u8]| {
+    let (public, private) = data.split_at(data.len() / 2);
+    let publictext = String::from_utf8_lossy(public);
+    let privatetext = String::from_utf8_lossy(private);
+    let _ = checkandextractkeys(&publictext, false);
+    let _ = checkandextractkeys(&privatetext, true);
+    let _ = checkandextractcombinedkeys(&publictext, false);
+    let _ = checkandextractcombinedkeys(&privatetext, true);
+    let _ = Combinedpub::try_from(publictext.as_ref());
+    let _ = Combinedcipher::try_from(data);
+    let _ = Combinedpub::from_bytes(data);
+    let _ = extractkyberkeys(public, private);
+    let _ = extractcombinedkeys(public, private);
+    let _ = publickeyfromder(public);
+    let _ = privatekeyfromder(private);
+    let _ = decryptprivatekey(private, b"passphrase");
+    let _ = encryptedkeysfromstr(&publictext, &privatetext, b"passphrase");
+    let _ = Combinedcipher::try_from(publictext.as_ref());
+    let _ = Combinedcipher::try_from(privatetext.as_ref());
+    for level in KyberLevel::ALL {
+        let _ = Authrequest::<Kyber, X25519>::from_bytes(*level, public);
+        let _ = Authresponse::<Kyber, X25519>::from_bytes(*level, private);
+    }
+    let _ = sign::Signpub::from_bytes(public);
+    let _ = sign::Signature::try_from(private);
+    let _ = sign::signpublicfromder(public);
+    let _ = sign::signprivatefromder(private);
+    let _ = sign::extractsignkeys(public, private);
+});
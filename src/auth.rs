@@ -0,0 +1,211 @@
+//! Authenticated exchange between two parties knowing each other's long-term [`Hybridpub`].
+//! Authentication is implicit: the initiator encapsulates to the long-term key of the responder, which encapsulates
+//! back to the long-term key of the initiator and to an ephemeral key of the initiator (for forward secrecy).
+//! Only the owners of both long-term keys can compute the shared secret, so a key swapped in the middle gives
+//! different secrets and the first authenticated message fails.
+//! ```rust
+//! use pqx::*;
+//! let alice_s = Combinedkey::new_static();
+//! let bob_s = Combinedkey::new_static();
+//! let (alice_p, bob_p) = (Combinedpub::new(&alice_s), Combinedpub::new(&bob_s));
+//! //Alice sends the request to Bob
+//! let (request, initiator) = Authrequest::new(&alice_s, &bob_p).unwrap();
+//! let request = Authrequest::from_bytes(bob_s.getlevel(), &request.to_bytes()).unwrap();
+//! //Bob sends the response back to Alice
+//! let (response, bob) = Authresponse::new(&bob_s, &alice_p, &request).unwrap();
+//! let response = Authresponse::from_bytes(alice_s.getlevel(), &response.to_bytes()).unwrap();
+//! let alice = initiator.finish(&alice_s, &response).unwrap();
+//! assert!(alice.getshared(SHAREDSIZE::High) == bob.getshared(SHAREDSIZE::High));
+//! ```
+use alloc::vec::Vec;
+use rand::{CryptoRng, RngCore};
+use sha3::{Digest, Sha3_256};
+
+use crate::{
+    Combinedshared, Combiner, DhGroup, Hybridcipher, Hybridkey, Hybridpub, Kem, Kemlevel, PqxError,
+};
+/// Domain separation label of the authenticated exchange
+const AUTHLABEL: &[u8] = b"PQX-Kyber-X25519-authenticated";
+/// First message, sent by the initiator: a cipher for the long-term key of the responder and an ephemeral public key
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Authrequest<K: Kem, D: DhGroup> {
+    cipher: Hybridcipher<K, D>,
+    ephemeral: Hybridpub<K, D>,
+}
+/// Second message, sent by the responder: ciphers for the long-term and the ephemeral keys of the initiator
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Authresponse<K: Kem, D: DhGroup> {
+    identity: Hybridcipher<K, D>,
+    ephemeral: Hybridcipher<K, D>,
+}
+/// State kept by the initiator until the response is received
+pub struct Authinitiator<K: Kem, D: DhGroup> {
+    ephemeral: Hybridkey<K, D>,
+    transcript: Sha3_256,
+    first: Combinedshared,
+}
+impl<K: Kem, D: DhGroup> Authrequest<K, D> {
+    /// Start an exchange from the long-term key of the initiator to the long-term public key of the responder.
+    /// Both keys must have the same level.
+    #[cfg(feature = "std")]
+    pub fn new(
+        identity: &Hybridkey<K, D>,
+        peer: &Hybridpub<K, D>,
+    ) -> Result<(Self, Authinitiator<K, D>), PqxError> {
+        Self::new_with_rng(identity, peer, &mut rand::thread_rng())
+    }
+    /// Start an exchange with the given random generator
+    pub fn new_with_rng<R: RngCore + CryptoRng>(
+        identity: &Hybridkey<K, D>,
+        peer: &Hybridpub<K, D>,
+        rng: &mut R,
+    ) -> Result<(Self, Authinitiator<K, D>), PqxError> {
+        let level = identity.getlevel();
+        if level != peer.getlevel() {
+            return Err(PqxError::KeyMismatch);
+        }
+        let (cipher, first) = Hybridcipher::encapsulate_with_rng(peer, Combiner::Transcript, rng)?;
+        let ephemeral = Hybridkey::generate_with_rng(level, false, rng);
+        let request = Authrequest {
+            cipher,
+            ephemeral: Hybridpub::new(&ephemeral),
+        };
+        let initiator = Authinitiator {
+            ephemeral,
+            transcript: transcript(&Hybridpub::new(identity), peer, &request),
+            first,
+        };
+        Ok((request, initiator))
+    }
+    /// Encode the request to be sent, as the cipher followed by the ephemeral public key
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.cipher.getcipher().to_vec();
+        bytes.extend_from_slice(self.ephemeral.getkem());
+        bytes.extend_from_slice(self.ephemeral.dh.as_ref());
+        bytes
+    }
+    /// Decode a request for a long-term key of the given level
+    pub fn from_bytes(level: K::Level, bytes: &[u8]) -> Result<Self, PqxError> {
+        let len = level.ciphertextbytes() + D::PUBLICBYTES;
+        if bytes.len() <= len + D::PUBLICBYTES {
+            return Err(PqxError::InvalidLength(bytes.len()));
+        }
+        let (cipher, ephemeral) = bytes.split_at(len);
+        let (kem, dh) = ephemeral.split_at(ephemeral.len() - D::PUBLICBYTES);
+        Ok(Authrequest {
+            cipher: Hybridcipher::try_from(cipher)?,
+            ephemeral: Hybridpub::fromparts(Some(level), kem, dh)?,
+        })
+    }
+}
+impl<K: Kem, D: DhGroup> Authresponse<K, D> {
+    /// Answer a request with the long-term key of the responder and the long-term public key of the initiator,
+    /// giving the response to send back and the shared secret
+    #[cfg(feature = "std")]
+    pub fn new(
+        identity: &Hybridkey<K, D>,
+        peer: &Hybridpub<K, D>,
+        request: &Authrequest<K, D>,
+    ) -> Result<(Self, Combinedshared), PqxError> {
+        Self::new_with_rng(identity, peer, request, &mut rand::thread_rng())
+    }
+    /// Answer a request with the given random generator
+    pub fn new_with_rng<R: RngCore + CryptoRng>(
+        identity: &Hybridkey<K, D>,
+        peer: &Hybridpub<K, D>,
+        request: &Authrequest<K, D>,
+        rng: &mut R,
+    ) -> Result<(Self, Combinedshared), PqxError> {
+        if identity.getlevel() != peer.getlevel() || request.ephemeral.getlevel() != peer.getlevel()
+        {
+            return Err(PqxError::KeyMismatch);
+        }
+        let first = Combinedshared::decapsulate(identity, &request.cipher, Combiner::Transcript)?;
+        let (cipher, second) = Hybridcipher::encapsulate_with_rng(peer, Combiner::Transcript, rng)?;
+        let (ephemeral, third) =
+            Hybridcipher::encapsulate_with_rng(&request.ephemeral, Combiner::Transcript, rng)?;
+        let response = Authresponse {
+            identity: cipher,
+            ephemeral,
+        };
+        let shared = finalize(
+            transcript(peer, &Hybridpub::new(identity), request),
+            &response,
+            [&first, &second, &third],
+        );
+        Ok((response, shared))
+    }
+    /// Encode the response to be sent, as both ciphers
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.identity.getcipher().to_vec();
+        bytes.extend_from_slice(self.ephemeral.getcipher());
+        bytes
+    }
+    /// Decode a response for a long-term key of the given level
+    pub fn from_bytes(level: K::Level, bytes: &[u8]) -> Result<Self, PqxError> {
+        let len = level.ciphertextbytes() + D::PUBLICBYTES;
+        if bytes.len() != 2 * len {
+            return Err(PqxError::InvalidLength(bytes.len()));
+        }
+        let (identity, ephemeral) = bytes.split_at(len);
+        Ok(Authresponse {
+            identity: Hybridcipher::try_from(identity)?,
+            ephemeral: Hybridcipher::try_from(ephemeral)?,
+        })
+    }
+}
+impl<K: Kem, D: DhGroup> Authinitiator<K, D> {
+    /// Get the shared secret from the response of the peer, with the long-term key that started the exchange
+    pub fn finish(
+        self,
+        identity: &Hybridkey<K, D>,
+        response: &Authresponse<K, D>,
+    ) -> Result<Combinedshared, PqxError> {
+        let second =
+            Combinedshared::decapsulate(identity, &response.identity, Combiner::Transcript)?;
+        let third = Combinedshared::decapsulate(
+            &self.ephemeral,
+            &response.ephemeral,
+            Combiner::Transcript,
+        )?;
+        Ok(finalize(
+            self.transcript,
+            response,
+            [&self.first, &second, &third],
+        ))
+    }
+}
+/// Hash both long-term public keys and the request
+fn transcript<K: Kem, D: DhGroup>(
+    initiator: &Hybridpub<K, D>,
+    responder: &Hybridpub<K, D>,
+    request: &Authrequest<K, D>,
+) -> Sha3_256 {
+    let mut sha = Sha3_256::new();
+    sha.update(AUTHLABEL);
+    for public in [initiator, responder, &request.ephemeral] {
+        sha.update(public.getkem());
+        sha.update(public.dh.as_ref());
+    }
+    sha.update(request.cipher.getcipher());
+    sha
+}
+/// Hash the response and the three secrets after the transcript
+fn finalize<K: Kem, D: DhGroup>(
+    mut sha: Sha3_256,
+    response: &Authresponse<K, D>,
+    secrets: [&Combinedshared; 3],
+) -> Combinedshared {
+    sha.update(response.identity.getcipher());
+    sha.update(response.ephemeral.getcipher());
+    for secret in secrets {
+        sha.update(secret.secret());
+    }
+    let hash = sha.finalize();
+    let mut result = Combinedshared {
+        shared: [0u8; Combinedshared::SHAREDLEN],
+        len: hash.len(),
+    };
+    result.shared[..hash.len()].copy_from_slice(&hash);
+    result
+}
@@ -0,0 +1,83 @@
+//! Diffie-Hellman groups used as the classical half of a hybrid key.
+//! X25519 is used by default, other groups (X448, P-256...) can be plugged by implementing [`DhGroup`].
+use core::fmt::Debug;
+use rand::{CryptoRng, RngCore};
+use x25519_dalek::{PublicKey, SharedSecret, StaticSecret};
+use zeroize::Zeroize;
+
+use crate::PqxError;
+/// Diffie-Hellman group used as the classical half of a hybrid key
+pub trait DhGroup {
+    /// Secret key, wiped on drop
+    type Secret: Zeroize;
+    /// Public key
+    type Public: AsRef<[u8]> + Clone + Debug + PartialEq + Eq + Zeroize;
+    /// Shared secret, wiped on drop
+    type Shared: AsRef<[u8]>;
+    /// Size in bytes of the public key
+    const PUBLICBYTES: usize;
+    /// Size in bytes of the shared secret
+    const SSBYTES: usize;
+    /// Generate a random secret key
+    fn generate<R: RngCore + CryptoRng>(rng: &mut R) -> Self::Secret;
+    /// Public key of a secret key
+    fn public(secret: &Self::Secret) -> Self::Public;
+    /// Read a public key of `PUBLICBYTES` bytes
+    fn publicfrombytes(bytes: &[u8]) -> Result<Self::Public, PqxError>;
+    /// Diffie-Hellman between our secret key and the peer public key
+    fn diffie_hellman(secret: &Self::Secret, public: &Self::Public) -> Self::Shared;
+    /// Check that a peer public key can be used, every key is accepted by default
+    fn checkpublic(_public: &Self::Public) -> bool {
+        true
+    }
+    /// Check that the peer contributed to the shared secret, by default that it is not all-zero
+    fn iscontributory(shared: &Self::Shared) -> bool {
+        shared.as_ref().iter().fold(0, |acc, byte| acc | byte) != 0
+    }
+}
+/// Diffie-Hellman with a peer public key, failing if the peer did not contribute to the shared secret
+pub(crate) fn agree<D: DhGroup>(
+    secret: &D::Secret,
+    public: &D::Public,
+) -> Result<D::Shared, PqxError> {
+    let shared = D::diffie_hellman(secret, public);
+    match D::iscontributory(&shared) {
+        true => Ok(shared),
+        false => Err(PqxError::InvalidPublicKey),
+    }
+}
+/// X25519 (RFC 7748), the default group of the hybrid keys
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct X25519;
+impl DhGroup for X25519 {
+    type Secret = StaticSecret;
+    type Public = PublicKey;
+    type Shared = SharedSecret;
+    const PUBLICBYTES: usize = 32;
+    const SSBYTES: usize = 32;
+    fn generate<R: RngCore + CryptoRng>(rng: &mut R) -> StaticSecret {
+        StaticSecret::random_from_rng(rng)
+    }
+    fn public(secret: &StaticSecret) -> PublicKey {
+        PublicKey::from(secret)
+    }
+    fn publicfrombytes(bytes: &[u8]) -> Result<PublicKey, PqxError> {
+        match <[u8; 32]>::try_from(bytes) {
+            Ok(bytes) => Ok(PublicKey::from(bytes)),
+            Err(_) => Err(PqxError::InvalidLength(bytes.len())),
+        }
+    }
+    fn diffie_hellman(secret: &StaticSecret, public: &PublicKey) -> SharedSecret {
+        secret.diffie_hellman(public)
+    }
+    /// Reject the points of small order. Clamped scalars are multiples of the cofactor,
+    /// so any of them gives an all-zero secret with such a point.
+    fn checkpublic(public: &PublicKey) -> bool {
+        StaticSecret::from([1u8; 32])
+            .diffie_hellman(public)
+            .was_contributory()
+    }
+    fn iscontributory(shared: &SharedSecret) -> bool {
+        shared.was_contributory()
+    }
+}
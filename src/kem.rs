@@ -0,0 +1,515 @@
+//! Kyber backend supporting every security level at runtime.
+//! The level is carried by the keys and ciphers, so one binary can talk Kyber-512 to constrained peers
+//! and Kyber-1024 to high-assurance peers.
+//! With the `mlkem` feature, ML-KEM as standardized in FIPS 203 is available as additional levels.
+//! Its keys and ciphers have the same sizes as round 3 Kyber, but both do not interoperate.
+use core::fmt::Debug;
+use libcrux_ml_kem::{
+    kyber1024, kyber512, kyber768, MlKemCiphertext, MlKemPrivateKey, MlKemPublicKey,
+    KEY_GENERATION_SEED_SIZE, SHARED_SECRET_SIZE,
+};
+#[cfg(feature = "mlkem")]
+use libcrux_ml_kem::{mlkem1024, mlkem768};
+use rand::{CryptoRng, RngCore};
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+
+use crate::PqxError;
+/// Size of the Kyber shared secret, whatever the level
+pub const KYBER_SSBYTES: usize = SHARED_SECRET_SIZE;
+/// Size of the seed of a Kyber keypair (`d || z` in FIPS 203)
+pub const KYBER_SEEDBYTES: usize = KEY_GENERATION_SEED_SIZE;
+/// Size of the biggest Kyber public key (Kyber-1024)
+pub const KYBER_MAXPUBLICKEYBYTES: usize = KyberLevel::Kyber1024.publickeybytes();
+/// Size of the biggest Kyber secret key (Kyber-1024)
+pub const KYBER_MAXSECRETKEYBYTES: usize = KyberLevel::Kyber1024.secretkeybytes();
+/// Size of the biggest Kyber cipher (Kyber-1024)
+pub const KYBER_MAXCIPHERTEXTBYTES: usize = KyberLevel::Kyber1024.ciphertextbytes();
+/// Parameter set of a [`Kem`], carried by its keys and ciphers
+pub trait Kemlevel: Copy + Debug + Default + PartialEq + Eq {
+    /// Name of the level, as used in serialized forms
+    fn name(self) -> &'static str;
+    /// Get the level from its name
+    fn fromname(name: &str) -> Option<Self>;
+    /// Size in bytes of the cipher
+    fn ciphertextbytes(self) -> usize;
+    /// Get the level from the size of a cipher
+    fn fromciphertextlen(len: usize) -> Option<Self>;
+}
+/// Key encapsulation mechanism used as the post-quantum half of a hybrid key.
+/// Its ciphers, followed by the Diffie-Hellman public key, must fit in [`crate::MAXCIPHERBYTES`].
+pub trait Kem {
+    /// Parameter set of the KEM
+    type Level: Kemlevel;
+    /// Keypair, holding the secret key
+    type Keypair: Clone + Debug + PartialEq + Eq + Zeroize;
+    /// Public key
+    type Public: AsRef<[u8]> + Clone + Debug + PartialEq + Eq + Zeroize;
+    /// Shared secret, wiped on drop
+    type Shared: AsRef<[u8]> + Clone + Debug + PartialEq + Eq + Zeroize;
+    /// Size in bytes of the shared secret
+    const SSBYTES: usize;
+    /// Generate a random keypair of the given level
+    fn generate<R: RngCore + CryptoRng>(level: Self::Level, rng: &mut R) -> Self::Keypair;
+    /// Level of a keypair
+    fn level(keypair: &Self::Keypair) -> Self::Level;
+    /// Public key of a keypair
+    fn public(keypair: &Self::Keypair) -> Self::Public;
+    /// Level of a public key
+    fn publiclevel(public: &Self::Public) -> Self::Level;
+    /// Read a public key of the given level, or of the level given by its size if `None`
+    fn publicfrombytes(level: Option<Self::Level>, bytes: &[u8]) -> Result<Self::Public, PqxError>;
+    /// Encapsulate a random secret for the public key, writing the cipher into `cipher` (of `ciphertextbytes()` bytes)
+    fn encapsulate<R: RngCore + CryptoRng>(
+        public: &Self::Public,
+        cipher: &mut [u8],
+        rng: &mut R,
+    ) -> Result<Self::Shared, PqxError>;
+    /// Decapsulate the secret from the cipher with the keypair
+    fn decapsulate(keypair: &Self::Keypair, cipher: &[u8]) -> Result<Self::Shared, PqxError>;
+}
+/// Security level of Kyber
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum KyberLevel {
+    /// Kyber-512, aims at security roughly equivalent to AES-128
+    Kyber512,
+    /// Kyber-768, aims at security roughly equivalent to AES-192
+    #[default]
+    Kyber768,
+    /// Kyber-1024, aims at security roughly equivalent to AES-256
+    Kyber1024,
+    /// ML-KEM-768 (FIPS 203), same sizes as Kyber-768
+    #[cfg(feature = "mlkem")]
+    MlKem768,
+    /// ML-KEM-1024 (FIPS 203), same sizes as Kyber-1024
+    #[cfg(feature = "mlkem")]
+    MlKem1024,
+}
+impl KyberLevel {
+    /// Every supported level
+    pub const ALL: &'static [KyberLevel] = &[
+        KyberLevel::Kyber512,
+        KyberLevel::Kyber768,
+        KyberLevel::Kyber1024,
+        #[cfg(feature = "mlkem")]
+        KyberLevel::MlKem768,
+        #[cfg(feature = "mlkem")]
+        KyberLevel::MlKem1024,
+    ];
+    /// Round 3 Kyber level with the same parameters
+    const fn parameters(self) -> KyberLevel {
+        match self {
+            #[cfg(feature = "mlkem")]
+            KyberLevel::MlKem768 => KyberLevel::Kyber768,
+            #[cfg(feature = "mlkem")]
+            KyberLevel::MlKem1024 => KyberLevel::Kyber1024,
+            level => level,
+        }
+    }
+    /// Check if the level is ML-KEM (FIPS 203) rather than round 3 Kyber
+    pub const fn ismlkem(self) -> bool {
+        !matches!(
+            self,
+            KyberLevel::Kyber512 | KyberLevel::Kyber768 | KyberLevel::Kyber1024
+        )
+    }
+    /// Size in bytes of the public key
+    pub const fn publickeybytes(self) -> usize {
+        match self.parameters() {
+            KyberLevel::Kyber512 => 800,
+            KyberLevel::Kyber768 => 1184,
+            _ => 1568,
+        }
+    }
+    /// Size in bytes of the secret key
+    pub const fn secretkeybytes(self) -> usize {
+        match self.parameters() {
+            KyberLevel::Kyber512 => 1632,
+            KyberLevel::Kyber768 => 2400,
+            _ => 3168,
+        }
+    }
+    /// Size in bytes of the cipher
+    pub const fn ciphertextbytes(self) -> usize {
+        match self.parameters() {
+            KyberLevel::Kyber512 => 768,
+            KyberLevel::Kyber768 => 1088,
+            _ => 1568,
+        }
+    }
+    /// Name of the level, as used in serialized forms (`kyber768`)
+    pub const fn name(self) -> &'static str {
+        match self {
+            KyberLevel::Kyber512 => "kyber512",
+            KyberLevel::Kyber768 => "kyber768",
+            KyberLevel::Kyber1024 => "kyber1024",
+            #[cfg(feature = "mlkem")]
+            KyberLevel::MlKem768 => "mlkem768",
+            #[cfg(feature = "mlkem")]
+            KyberLevel::MlKem1024 => "mlkem1024",
+        }
+    }
+    /// Get the level from its name (case insensitive)
+    pub fn fromname(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|level| level.name().eq_ignore_ascii_case(name))
+    }
+    /// Identifiers of the algorithm (1 for round 3 Kyber, 2 for ML-KEM) and of the NIST security category
+    /// of the level, as used by [`crate::Combinedpub::to_bytes`]
+    pub(crate) const fn wireid(self) -> (u8, u8) {
+        let category = match self.parameters() {
+            KyberLevel::Kyber512 => 1,
+            KyberLevel::Kyber768 => 3,
+            _ => 5,
+        };
+        match self.ismlkem() {
+            false => (1, category),
+            true => (2, category),
+        }
+    }
+    /// Get the level from the identifiers of [`KyberLevel::wireid`]
+    pub(crate) fn fromwireid(algorithm: u8, category: u8) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|level| level.wireid() == (algorithm, category))
+    }
+    /// Get the level from the size of a public key.
+    /// As ML-KEM has the same sizes, this always gives round 3 Kyber.
+    pub fn frompublickeylen(len: usize) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|level| level.publickeybytes() == len)
+    }
+    /// Get the level from the size of a secret key.
+    /// As ML-KEM has the same sizes, this always gives round 3 Kyber.
+    pub fn fromsecretkeylen(len: usize) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|level| level.secretkeybytes() == len)
+    }
+    /// Get the level from the size of a cipher.
+    /// As ML-KEM has the same sizes, this always gives round 3 Kyber.
+    pub fn fromciphertextlen(len: usize) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|level| level.ciphertextbytes() == len)
+    }
+}
+impl Kemlevel for KyberLevel {
+    fn name(self) -> &'static str {
+        KyberLevel::name(self)
+    }
+    fn fromname(name: &str) -> Option<Self> {
+        KyberLevel::fromname(name)
+    }
+    fn ciphertextbytes(self) -> usize {
+        KyberLevel::ciphertextbytes(self)
+    }
+    fn fromciphertextlen(len: usize) -> Option<Self> {
+        KyberLevel::fromciphertextlen(len)
+    }
+}
+/// Call the libcrux module of the given level
+macro_rules! kyber {
+    ($level:expr, $module:ident => $body:expr) => {
+        match $level {
+            KyberLevel::Kyber512 => {
+                use kyber512 as $module;
+                $body
+            }
+            KyberLevel::Kyber768 => {
+                use kyber768 as $module;
+                $body
+            }
+            KyberLevel::Kyber1024 => {
+                use kyber1024 as $module;
+                $body
+            }
+            #[cfg(feature = "mlkem")]
+            KyberLevel::MlKem768 => {
+                use mlkem768 as $module;
+                $body
+            }
+            #[cfg(feature = "mlkem")]
+            KyberLevel::MlKem1024 => {
+                use mlkem1024 as $module;
+                $body
+            }
+        }
+    };
+}
+/// Wipe a libcrux private key once it is no longer needed
+fn wipe<const SIZE: usize>(key: MlKemPrivateKey<SIZE>) {
+    let mut raw: [u8; SIZE] = key.into();
+    raw.zeroize();
+}
+/// Kyber keypair of any level
+#[derive(Clone, Debug, Zeroize, ZeroizeOnDrop)]
+pub struct Kyberkeypair {
+    #[zeroize(skip)]
+    level: KyberLevel,
+    public: [u8; KYBER_MAXPUBLICKEYBYTES],
+    secret: [u8; KYBER_MAXSECRETKEYBYTES],
+    seed: Option<[u8; KYBER_SEEDBYTES]>,
+}
+/// Keypairs are equal if their keys are, whether the seed is known or not
+impl PartialEq for Kyberkeypair {
+    fn eq(&self, other: &Self) -> bool {
+        self.level == other.level
+            && self.getpublic() == other.getpublic()
+            && self.getsecret() == other.getsecret()
+    }
+}
+impl Eq for Kyberkeypair {}
+impl Kyberkeypair {
+    /// Generate a random keypair of the given level
+    pub fn generate<R: RngCore + CryptoRng>(level: KyberLevel, rng: &mut R) -> Self {
+        let mut seed = [0u8; KYBER_SEEDBYTES];
+        rng.fill_bytes(&mut seed);
+        let result = Self::fromseed(level, &seed);
+        seed.zeroize();
+        result
+    }
+    /// Derive the keypair of the given level from a seed `d || z`, as `ML-KEM.KeyGen_internal` of FIPS 203
+    pub fn fromseed(level: KyberLevel, seed: &[u8; KYBER_SEEDBYTES]) -> Self {
+        let mut randomness = *seed;
+        let mut result = Kyberkeypair {
+            level,
+            public: [0u8; KYBER_MAXPUBLICKEYBYTES],
+            secret: [0u8; KYBER_MAXSECRETKEYBYTES],
+            seed: Some(*seed),
+        };
+        kyber!(level, m => {
+            let (secret, public) = m::generate_key_pair(randomness).into_parts();
+            result.public[..level.publickeybytes()].copy_from_slice(public.as_ref());
+            result.secret[..level.secretkeybytes()].copy_from_slice(secret.as_ref());
+            wipe(secret);
+        });
+        randomness.zeroize();
+        result
+    }
+    /// Create a round 3 Kyber keypair from its public and secret keys, the level is given by their sizes.
+    /// The keys are checked to match by encapsulating and decapsulating a secret.
+    #[cfg(feature = "std")]
+    pub fn new(public: &[u8], secret: &[u8]) -> Result<Self, PqxError> {
+        Self::new_with_rng(public, secret, &mut rand::thread_rng())
+    }
+    /// Create a round 3 Kyber keypair from its public and secret keys, checked with the given random generator
+    pub fn new_with_rng<R: RngCore + CryptoRng>(
+        public: &[u8],
+        secret: &[u8],
+        rng: &mut R,
+    ) -> Result<Self, PqxError> {
+        match KyberLevel::frompublickeylen(public.len()) {
+            Some(level) => Self::withlevel_with_rng(level, public, secret, rng),
+            None => Err(PqxError::InvalidLength(public.len())),
+        }
+    }
+    /// Create a keypair of the given level from its public and secret keys.
+    /// The keys are checked to match by encapsulating and decapsulating a secret.
+    #[cfg(feature = "std")]
+    pub fn withlevel(level: KyberLevel, public: &[u8], secret: &[u8]) -> Result<Self, PqxError> {
+        Self::withlevel_with_rng(level, public, secret, &mut rand::thread_rng())
+    }
+    /// Create a keypair of the given level from its public and secret keys, checked with the given random generator
+    pub fn withlevel_with_rng<R: RngCore + CryptoRng>(
+        level: KyberLevel,
+        public: &[u8],
+        secret: &[u8],
+        rng: &mut R,
+    ) -> Result<Self, PqxError> {
+        if level.publickeybytes() != public.len() {
+            return Err(PqxError::InvalidLength(public.len()));
+        }
+        if level.secretkeybytes() != secret.len() {
+            return Err(PqxError::InvalidLength(secret.len()));
+        }
+        let mut result = Kyberkeypair {
+            level,
+            public: [0u8; KYBER_MAXPUBLICKEYBYTES],
+            secret: [0u8; KYBER_MAXSECRETKEYBYTES],
+            seed: None,
+        };
+        result.public[..public.len()].copy_from_slice(public);
+        result.secret[..secret.len()].copy_from_slice(secret);
+        //Try to encapsulate and decapsule to verify secret key matches public key
+        let (cipher, mut shared_secret) = encapsulate(level, public, rng)?;
+        let mut expected_shared_secret =
+            decapsulate(level, &cipher[..level.ciphertextbytes()], secret)?;
+        let matching = expected_shared_secret == shared_secret;
+        shared_secret.zeroize();
+        expected_shared_secret.zeroize();
+        match matching {
+            true => Ok(result),
+            false => Err(PqxError::KeyMismatch),
+        }
+    }
+    /// Create a keypair of the given level from its secret key alone, as the secret key contains the public key
+    #[cfg(feature = "std")]
+    pub fn fromsecret(level: KyberLevel, secret: &[u8]) -> Result<Self, PqxError> {
+        Self::fromsecret_with_rng(level, secret, &mut rand::thread_rng())
+    }
+    /// Create a keypair of the given level from its secret key alone, checked with the given random generator
+    pub fn fromsecret_with_rng<R: RngCore + CryptoRng>(
+        level: KyberLevel,
+        secret: &[u8],
+        rng: &mut R,
+    ) -> Result<Self, PqxError> {
+        if level.secretkeybytes() != secret.len() {
+            return Err(PqxError::InvalidLength(secret.len()));
+        }
+        //The secret key is s || public key || H(public key) || z, with both hashes of 32 bytes
+        let start = level.secretkeybytes() - level.publickeybytes() - 2 * KYBER_SSBYTES;
+        Self::withlevel_with_rng(
+            level,
+            &secret[start..start + level.publickeybytes()],
+            secret,
+            rng,
+        )
+    }
+    /// Level of the keypair
+    pub fn getlevel(&self) -> KyberLevel {
+        self.level
+    }
+    /// Public key
+    pub fn getpublic(&self) -> &[u8] {
+        &self.public[..self.level.publickeybytes()]
+    }
+    /// Secret key, keep it safe
+    pub fn getsecret(&self) -> &[u8] {
+        &self.secret[..self.level.secretkeybytes()]
+    }
+    /// Seed `d || z` the keypair was derived from, unknown if it was built from its keys
+    pub fn getseed(&self) -> Option<&[u8; KYBER_SEEDBYTES]> {
+        self.seed.as_ref()
+    }
+}
+/// Kyber public key of any level
+#[derive(Clone, Debug, PartialEq, Eq, Zeroize)]
+pub struct Kyberpublic {
+    #[zeroize(skip)]
+    level: KyberLevel,
+    key: [u8; KYBER_MAXPUBLICKEYBYTES],
+}
+impl Kyberpublic {
+    /// Level of the public key
+    pub fn getlevel(&self) -> KyberLevel {
+        self.level
+    }
+}
+impl AsRef<[u8]> for Kyberpublic {
+    fn as_ref(&self) -> &[u8] {
+        &self.key[..self.level.publickeybytes()]
+    }
+}
+/// Kyber (or ML-KEM with the `mlkem` feature) at any level, the default KEM of the hybrid keys
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Kyber;
+impl Kem for Kyber {
+    type Level = KyberLevel;
+    type Keypair = Kyberkeypair;
+    type Public = Kyberpublic;
+    type Shared = Zeroizing<[u8; KYBER_SSBYTES]>;
+    const SSBYTES: usize = KYBER_SSBYTES;
+    fn generate<R: RngCore + CryptoRng>(level: KyberLevel, rng: &mut R) -> Kyberkeypair {
+        Kyberkeypair::generate(level, rng)
+    }
+    fn level(keypair: &Kyberkeypair) -> KyberLevel {
+        keypair.getlevel()
+    }
+    fn public(keypair: &Kyberkeypair) -> Kyberpublic {
+        let mut key = [0u8; KYBER_MAXPUBLICKEYBYTES];
+        key[..keypair.getpublic().len()].copy_from_slice(keypair.getpublic());
+        Kyberpublic {
+            level: keypair.getlevel(),
+            key,
+        }
+    }
+    fn publiclevel(public: &Kyberpublic) -> KyberLevel {
+        public.level
+    }
+    /// Without level, the key is read as round 3 Kyber as ML-KEM keys have the same sizes
+    fn publicfrombytes(level: Option<KyberLevel>, bytes: &[u8]) -> Result<Kyberpublic, PqxError> {
+        let level = match level.or(KyberLevel::frompublickeylen(bytes.len())) {
+            Some(level) if level.publickeybytes() == bytes.len() => level,
+            _ => return Err(PqxError::InvalidLength(bytes.len())),
+        };
+        if !validatepublic(level, bytes) {
+            return Err(PqxError::InvalidPublicKey);
+        }
+        let mut key = [0u8; KYBER_MAXPUBLICKEYBYTES];
+        key[..bytes.len()].copy_from_slice(bytes);
+        Ok(Kyberpublic { level, key })
+    }
+    fn encapsulate<R: RngCore + CryptoRng>(
+        public: &Kyberpublic,
+        cipher: &mut [u8],
+        rng: &mut R,
+    ) -> Result<Self::Shared, PqxError> {
+        let len = public.level.ciphertextbytes();
+        if cipher.len() != len {
+            return Err(PqxError::InvalidLength(cipher.len()));
+        }
+        let (result, shared) = encapsulate(public.level, public.as_ref(), rng)?;
+        cipher.copy_from_slice(&result[..len]);
+        Ok(Zeroizing::new(shared))
+    }
+    fn decapsulate(keypair: &Kyberkeypair, cipher: &[u8]) -> Result<Self::Shared, PqxError> {
+        decapsulate(keypair.getlevel(), cipher, keypair.getsecret()).map(Zeroizing::new)
+    }
+}
+/// Check that every coefficient of the public key is reduced modulo q (modulus check of FIPS 203)
+fn validatepublic(level: KyberLevel, public: &[u8]) -> bool {
+    kyber!(level, m => match MlKemPublicKey::try_from(public) {
+        Ok(public) => m::validate_public_key(&public),
+        Err(_) => false,
+    })
+}
+/// Encapsulate a random secret for the public key, returning the cipher (padded to the biggest size) and the secret
+pub(crate) fn encapsulate<R: RngCore + CryptoRng>(
+    level: KyberLevel,
+    public: &[u8],
+    rng: &mut R,
+) -> Result<([u8; KYBER_MAXCIPHERTEXTBYTES], [u8; KYBER_SSBYTES]), PqxError> {
+    let mut randomness = [0u8; SHARED_SECRET_SIZE];
+    rng.fill_bytes(&mut randomness);
+    let mut cipher = [0u8; KYBER_MAXCIPHERTEXTBYTES];
+    let result = kyber!(level, m => {
+        match MlKemPublicKey::try_from(public) {
+            Ok(public) => {
+                let (ciphertext, shared) = m::encapsulate(&public, randomness);
+                cipher[..level.ciphertextbytes()].copy_from_slice(ciphertext.as_ref());
+                Ok((cipher, shared))
+            }
+            Err(_) => Err(PqxError::InvalidLength(public.len())),
+        }
+    });
+    randomness.zeroize();
+    result
+}
+/// Decapsulate the secret from the cipher with the secret key
+pub(crate) fn decapsulate(
+    level: KyberLevel,
+    cipher: &[u8],
+    secret: &[u8],
+) -> Result<[u8; KYBER_SSBYTES], PqxError> {
+    kyber!(level, m => {
+        match (MlKemPrivateKey::try_from(secret), MlKemCiphertext::try_from(cipher)) {
+            (Ok(secret), Ok(cipher)) => {
+                let shared = m::decapsulate(&secret, &cipher);
+                wipe(secret);
+                Ok(shared)
+            }
+            (Ok(secret), Err(_)) => {
+                wipe(secret);
+                Err(PqxError::InvalidLength(cipher.len()))
+            }
+            _ => Err(PqxError::InvalidLength(secret.len())),
+        }
+    })
+}
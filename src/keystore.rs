@@ -0,0 +1,294 @@
+//! Directory of named keys, for servers keeping a current key, the previous one while exchanges drain, or a key per tenant.
+//! ```rust
+//! use pqx::*;
+//! use pqx::keystore::Keystore;
+//! let directory = tempfile::tempdir().unwrap();
+//! let keystore = Keystore::open(directory.path()).unwrap();
+//! let key = Combinedkey::new_static();
+//! keystore.store("server", &key, false).unwrap();
+//! assert!(keystore.load("server").unwrap().checkkeys(&key));
+//! let rotated = keystore.rotate("server", KyberLevel::Kyber1024, true).unwrap();
+//! assert!(keystore.load("server.previous").unwrap().checkkeys(&key));
+//! assert!(keystore.load("server").unwrap().checkkeys(&rotated));
+//! assert_eq!(keystore.list().unwrap().len(), 2);
+//! ```
+use fs2::FileExt;
+use rand::{thread_rng, CryptoRng, RngCore};
+use std::fs::{self, File, OpenOptions};
+use std::io;
+#[cfg(target_family = "unix")]
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use zeroize::Zeroizing;
+
+use crate::key::{
+    combinedkeysfromstr_with_rng, combinedkeystostr, kyberkeysfromstr_with_rng, kyberkeystostr,
+    persistfile, persistkeys, removefile, stagefile, Stagedfile,
+};
+use crate::{Combinedkey, KyberLevel, PqxError};
+/// Lock file of the directory
+const LOCKFILE: &str = ".lock";
+/// Suffix of the name of the key kept by [`Keystore::rotate`]
+pub const PREVIOUS: &str = ".previous";
+/// Longest name of a key
+const MAXNAME: usize = 64;
+/// Extensions of the private key, public key and metadata files
+const PRIVATEEXT: &str = "key";
+const PUBLICEXT: &str = "pub";
+const METAEXT: &str = "meta";
+/// Metadata of a stored key
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Keyinfo {
+    /// Name of the key
+    pub name: String,
+    /// Algorithm of the Kyber key
+    pub level: KyberLevel,
+    /// If the X25519 secret is stored along the Kyber key
+    pub persistent: bool,
+    /// Time the key was stored
+    pub created: SystemTime,
+}
+/// Directory holding named keys, each as `name.key`, `name.pub` and `name.meta` files.
+/// Every operation locks the directory so that several processes can share it.
+#[derive(Clone, Debug)]
+pub struct Keystore {
+    directory: PathBuf,
+}
+impl Keystore {
+    /// Open the keystore, creating the directory (only readable by its owner) if needed
+    pub fn open<T: AsRef<Path>>(directory: T) -> Result<Self, PqxError> {
+        let directory = directory.as_ref().to_path_buf();
+        let mut builder = fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(target_family = "unix")]
+        builder.mode(0o700);
+        builder.create(&directory)?;
+        Ok(Keystore { directory })
+    }
+    /// Get the directory of the keystore
+    pub fn getdirectory(&self) -> &Path {
+        &self.directory
+    }
+    /// Store a key under a name, an existing key is only replaced if `overwrite` is set
+    pub fn store(&self, name: &str, key: &Combinedkey, overwrite: bool) -> Result<(), PqxError> {
+        checkname(name)?;
+        let _lock = self.lock(true)?;
+        self.write(name, key, overwrite)
+    }
+    /// Load a stored key, both halves being checked against each other
+    pub fn load(&self, name: &str) -> Result<Combinedkey, PqxError> {
+        self.load_with_rng(name, &mut thread_rng())
+    }
+    /// Load a stored key, checked with the given random generator, see [`Keystore::load`]
+    pub fn load_with_rng<R: RngCore + CryptoRng>(
+        &self,
+        name: &str,
+        rng: &mut R,
+    ) -> Result<Combinedkey, PqxError> {
+        checkname(name)?;
+        let _lock = self.lock(false)?;
+        let info = self.readinfo(name)?;
+        let private = Zeroizing::new(fs::read_to_string(self.path(name, PRIVATEEXT))?);
+        let public = fs::read_to_string(self.path(name, PUBLICEXT))?;
+        let key = match info.persistent {
+            true => combinedkeysfromstr_with_rng(&public, &private, rng)?,
+            false => kyberkeysfromstr_with_rng(&public, &private, rng)?,
+        };
+        match key.getlevel() == info.level && key.isstatic() == info.persistent {
+            true => Ok(key),
+            false => Err(PqxError::KeyMismatch),
+        }
+    }
+    /// Get the metadata of a stored key
+    pub fn info(&self, name: &str) -> Result<Keyinfo, PqxError> {
+        checkname(name)?;
+        let _lock = self.lock(false)?;
+        self.readinfo(name)
+    }
+    /// List the stored keys, sorted by name.
+    /// Fails, with the error of that entry, if the metadata of any key cannot be read or parsed.
+    pub fn list(&self) -> Result<Vec<Keyinfo>, PqxError> {
+        let _lock = self.lock(false)?;
+        let mut keys = Vec::new();
+        for entry in fs::read_dir(&self.directory)? {
+            let path = entry?.path();
+            let name = match (path.extension(), path.file_stem().and_then(|s| s.to_str())) {
+                (Some(extension), Some(name)) if extension == METAEXT => name,
+                _ => continue,
+            };
+            if checkname(name).is_ok() {
+                keys.push(self.readinfo(name)?);
+            }
+        }
+        keys.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(keys)
+    }
+    /// Delete a stored key
+    pub fn delete(&self, name: &str) -> Result<(), PqxError> {
+        checkname(name)?;
+        let _lock = self.lock(true)?;
+        self.remove(name)
+    }
+    /// Replace a key by a new one of the given level. The former key is kept as `name.previous`
+    /// (see [`PREVIOUS`]) to end the exchanges in progress, replacing an older previous key.
+    pub fn rotate(
+        &self,
+        name: &str,
+        level: KyberLevel,
+        persistent: bool,
+    ) -> Result<Combinedkey, PqxError> {
+        self.rotate_with_rng(name, level, persistent, &mut thread_rng())
+    }
+    /// Replace a key by a new one generated from the given random generator, see [`Keystore::rotate`]
+    pub fn rotate_with_rng<R: RngCore + CryptoRng>(
+        &self,
+        name: &str,
+        level: KyberLevel,
+        persistent: bool,
+        rng: &mut R,
+    ) -> Result<Combinedkey, PqxError> {
+        checkname(name)?;
+        let previous = format!("{}{}", name, PREVIOUS);
+        checkname(&previous)?;
+        let _lock = self.lock(true)?;
+        //The new key is written to temporary files before the current key is moved, so that a failure keeps it
+        let key = Combinedkey::generate_with_rng(level, persistent, rng);
+        let (private, public, metadata) = self.stage(name, &key, false)?;
+        //The older previous key is set aside under a hidden name, not listed, until the new key is in place
+        let older = format!(".{}", previous);
+        let moved = self.path(name, METAEXT).exists();
+        let setaside = moved && self.path(&previous, METAEXT).exists();
+        if setaside {
+            self.rename(&previous, &older)?;
+        }
+        if moved {
+            if let Err(e) = self.rename(name, &previous) {
+                if setaside {
+                    let _ = self.rename(&older, &previous);
+                }
+                return Err(e);
+            }
+        }
+        if let Err(e) = persistkeys(private, public).and_then(|_| persistfile(metadata)) {
+            if moved {
+                let _ = self.rename(&previous, name);
+            }
+            if setaside {
+                let _ = self.rename(&older, &previous);
+            }
+            return Err(e);
+        }
+        if setaside {
+            self.remove(&older)?;
+        }
+        Ok(key)
+    }
+    /// Path of a file of a key
+    fn path(&self, name: &str, extension: &str) -> PathBuf {
+        self.directory.join(format!("{}.{}", name, extension))
+    }
+    /// Lock the directory, shared to read and exclusive to write. The lock is released when the file is dropped.
+    fn lock(&self, exclusive: bool) -> Result<File, PqxError> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.directory.join(LOCKFILE))?;
+        match exclusive {
+            true => FileExt::lock_exclusive(&file)?,
+            false => FileExt::lock_shared(&file)?,
+        }
+        Ok(file)
+    }
+    /// Write the key files then the metadata
+    fn write(&self, name: &str, key: &Combinedkey, overwrite: bool) -> Result<(), PqxError> {
+        if !overwrite && self.path(name, METAEXT).exists() {
+            return Err(PqxError::Io(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "key already exists",
+            )));
+        }
+        let (private, public, metadata) = self.stage(name, key, overwrite)?;
+        persistkeys(private, public)?;
+        persistfile(metadata)
+    }
+    /// Write the private key, public key and metadata files of a key to temporary files
+    fn stage(
+        &self,
+        name: &str,
+        key: &Combinedkey,
+        overwrite: bool,
+    ) -> Result<(Stagedfile, Stagedfile, Stagedfile), PqxError> {
+        let (private, public) = match key.isstatic() {
+            true => combinedkeystostr(key)?,
+            false => kyberkeystostr(key.getkyberkeypair())?,
+        };
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        let text = format!(
+            "algorithm: {}\nstatic: {}\ncreated: {}\n",
+            key.getlevel().name(),
+            key.isstatic(),
+            created
+        );
+        Ok((
+            stagefile(&self.path(name, PRIVATEEXT), &private, true, overwrite)?,
+            stagefile(&self.path(name, PUBLICEXT), &public, false, overwrite)?,
+            stagefile(&self.path(name, METAEXT), &text, false, overwrite)?,
+        ))
+    }
+    /// Read the metadata file of a key
+    fn readinfo(&self, name: &str) -> Result<Keyinfo, PqxError> {
+        let text = fs::read_to_string(self.path(name, METAEXT))?;
+        let (mut level, mut persistent, mut created) = (None, None, None);
+        for line in text.lines() {
+            match line.split_once(':').map(|(k, v)| (k.trim(), v.trim())) {
+                Some(("algorithm", value)) => level = KyberLevel::fromname(value),
+                Some(("static", value)) => persistent = value.parse::<bool>().ok(),
+                Some(("created", value)) => created = value.parse::<u64>().ok(),
+                _ => (),
+            }
+        }
+        let created =
+            created.and_then(|created| UNIX_EPOCH.checked_add(Duration::from_secs(created)));
+        match (level, persistent, created) {
+            (Some(level), Some(persistent), Some(created)) => Ok(Keyinfo {
+                name: name.to_string(),
+                level,
+                persistent,
+                created,
+            }),
+            _ => Err(PqxError::InvalidHeader),
+        }
+    }
+    /// Remove the files of a key, the metadata first so that a listed key is always complete
+    fn remove(&self, name: &str) -> Result<(), PqxError> {
+        for extension in [METAEXT, PRIVATEEXT, PUBLICEXT] {
+            removefile(&self.path(name, extension))?;
+        }
+        Ok(())
+    }
+    /// Rename the files of a key, the metadata last so that a listed key is always complete
+    fn rename(&self, from: &str, to: &str) -> Result<(), PqxError> {
+        for extension in [PRIVATEEXT, PUBLICEXT, METAEXT] {
+            fs::rename(self.path(from, extension), self.path(to, extension))?;
+        }
+        Ok(())
+    }
+}
+/// Check that a name only holds letters, digits, `-`, `_` and `.` and does not start with a `.`
+fn checkname(name: &str) -> Result<(), PqxError> {
+    let valid = !name.is_empty()
+        && name.len() <= MAXNAME
+        && !name.starts_with('.')
+        && !name.contains("..")
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+    match valid {
+        true => Ok(()),
+        false => Err(PqxError::InvalidInput),
+    }
+}
@@ -1,6 +1,6 @@
 //! Implement PQC-Kyber with X25519 to shared a secret key in a post-quantum resistant way.
 //! The test implements a way to implement the communication between Alice and bob.
-//! Be careful though, verification of public key is not performed here.
+//! Peer public keys are validated: malformed Kyber keys and X25519 points of small order give [`PqxError::InvalidPublicKey`].
 //! <div class="warning">This crate has not undergo any security audit and should be used with caution!</div>
 //!
 //! ```rust
@@ -14,137 +14,364 @@
 //!     let bob_s = Combinedkey::default();
 //!     let bob_p = Combinedpub::new(&bob_s);
 //!     sender1.send(bob_p.to_string()).unwrap();
-//!     let cipher = receiver2.recv_timeout(Duration::new(20,0)).unwrap();
-//!     let cipher = Combinedcipher::from(cipher);
+//!     let cipher: Vec<u8> = receiver2.recv_timeout(Duration::new(20,0)).unwrap();
+//!     let cipher = Combinedcipher::try_from(cipher.as_slice()).unwrap();
 //!     Combinedshared::new(bob_s, cipher).unwrap()
 //! });
 //! let alice_s = Combinedkey::default();
 //! let pubkey = receiver1.recv_timeout(Duration::new(20,0)).unwrap();
 //! let pubkey = Combinedpub::try_from(pubkey.as_str()).unwrap();
 //! let kyberelem = Combinedcipher::new(&alice_s,&pubkey).unwrap();
-//! sender2.send(kyberelem.getcipher()).unwrap();
+//! sender2.send(kyberelem.getcipher().to_vec()).unwrap();
 //! let shared = Combinedshared::getfromshared(kyberelem, pubkey, alice_s).unwrap().getshared(SHAREDSIZE::Med);
 //! let result = thread.join().unwrap().getshared(SHAREDSIZE::Med);
 //! assert!(shared==result,"Invalid shared, got {:#?} and {:#?}",shared,result);
 //! //println!("Valid shared, got {}",hex::encode(shared.get()))
 //! ```
+//! Kyber-768 is used by default, other levels are available with [`Combinedkey::generate`]:
+//! ```rust
+//! use pqx::*;
+//! let bob_s = Combinedkey::generate(KyberLevel::Kyber1024, false);
+//! let bob_p = Combinedpub::new(&bob_s);
+//! assert!(bob_p.to_string().starts_with("kyber1024|"));
+//! let (cipher, alice) = Combinedcipher::encapsulate(&bob_p).unwrap();
+//! assert_eq!(cipher.getlevel(), KyberLevel::Kyber1024);
+//! let bob = Combinedshared::new(bob_s, cipher).unwrap();
+//! assert!(alice.getshared(SHAREDSIZE::High) == bob.getshared(SHAREDSIZE::High));
+//! ```
+#![cfg_attr(not(feature = "std"), no_std)]
+extern crate alloc;
+mod auth;
+mod dh;
+mod kem;
 #[cfg(feature = "keystore")]
 pub mod key;
-use rand::{self, thread_rng};
-use safe_pqc_kyber::*;
+#[cfg(feature = "keystore")]
+pub mod keystore;
+#[cfg(feature = "serde")]
+mod serialize;
+#[cfg(feature = "sign")]
+pub mod sign;
+use alloc::{vec, vec::Vec};
+pub use auth::{Authinitiator, Authrequest, Authresponse};
+#[cfg(feature = "to_string")]
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use core::fmt::Display;
+use core::marker::PhantomData;
+use dh::agree;
+pub use dh::{DhGroup, X25519};
+use hkdf::Hkdf;
+pub use kem::{
+    Kem, Kemlevel, Kyber, KyberLevel, Kyberkeypair, Kyberpublic, KYBER_MAXCIPHERTEXTBYTES,
+    KYBER_MAXPUBLICKEYBYTES, KYBER_MAXSECRETKEYBYTES, KYBER_SEEDBYTES, KYBER_SSBYTES,
+};
+#[cfg(feature = "std")]
+use rand::thread_rng;
+use rand::{self, CryptoRng, RngCore};
 use sha2::*;
-use std::fmt::Display;
-use x25519_dalek::*;
-use zeroize::{Zeroize, ZeroizeOnDrop};
-/// PqxError gathers every error that can happen on this crate. There are opaque by design and distinguishs bad input or error in generation.
-#[derive(Debug, Clone, Copy)]
+use sha3::digest::{ExtendableOutput, XofReader};
+use sha3::{Sha3_256, Shake256};
+use x25519_dalek::StaticSecret;
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+/// PqxError gathers every error that can happen on this crate.
+/// Errors on secret data (decapsulation) stay opaque by design and never tell what went wrong.
+#[derive(Debug)]
 pub enum PqxError {
+    /// Error while generating or encapsulating a key
     KyberError,
+    /// Input is not valid
     InvalidInput,
+    /// Key or cipher does not have a valid length, the length received is given
+    InvalidLength(usize),
+    /// Hexadecimal encoding is not valid
+    #[cfg(any(feature = "to_string", feature = "keystore"))]
+    InvalidHex(hex::FromHexError),
+    /// Base64 encoding is not valid
+    #[cfg(feature = "to_string")]
+    InvalidBase64(base64::DecodeError),
+    /// Header or footer of a key file is not valid
+    InvalidHeader,
+    /// DER or PEM encoding of a key is not valid
+    #[cfg(feature = "keystore")]
+    InvalidDer(pkcs8::der::Error),
+    /// Keys do not match each other (secret and public keys, levels...)
+    KeyMismatch,
+    /// Decapsulation failed
+    DecapsulationError,
+    /// Encrypted key cannot be decrypted, the passphrase is wrong or the key is corrupted
+    InvalidPassphrase,
+    /// Public key of the peer is not valid (malformed KEM key, Diffie-Hellman point of small order)
+    InvalidPublicKey,
+    /// Signature does not match the message and the public key
+    InvalidSignature,
+    /// Version of the encoding is not supported, the version received is given
+    UnsupportedVersion(u8),
+    /// Algorithm or level of the peer is not supported
+    UnsupportedAlgorithm,
+    /// Error when reading or writing a key file
+    #[cfg(feature = "std")]
+    Io(std::io::Error),
+}
+impl Display for PqxError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            PqxError::KyberError => write!(f, "key generation or encapsulation failed"),
+            PqxError::InvalidInput => write!(f, "invalid input"),
+            PqxError::InvalidLength(len) => write!(f, "invalid length of {} bytes", len),
+            #[cfg(any(feature = "to_string", feature = "keystore"))]
+            PqxError::InvalidHex(_) => write!(f, "invalid hexadecimal encoding"),
+            #[cfg(feature = "to_string")]
+            PqxError::InvalidBase64(_) => write!(f, "invalid base64 encoding"),
+            PqxError::InvalidHeader => write!(f, "invalid header or footer in key file"),
+            #[cfg(feature = "keystore")]
+            PqxError::InvalidDer(_) => write!(f, "invalid DER or PEM encoding"),
+            PqxError::KeyMismatch => write!(f, "keys do not match"),
+            PqxError::DecapsulationError => write!(f, "decapsulation failed"),
+            PqxError::InvalidPassphrase => write!(f, "wrong passphrase or corrupted key"),
+            PqxError::InvalidPublicKey => write!(f, "invalid peer public key"),
+            PqxError::InvalidSignature => write!(f, "invalid signature"),
+            PqxError::UnsupportedVersion(version) => {
+                write!(f, "unsupported encoding version {}", version)
+            }
+            PqxError::UnsupportedAlgorithm => write!(f, "unsupported algorithm"),
+            #[cfg(feature = "std")]
+            PqxError::Io(_) => write!(f, "cannot read or write key file"),
+        }
+    }
+}
+#[cfg(feature = "std")]
+impl std::error::Error for PqxError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            #[cfg(any(feature = "to_string", feature = "keystore"))]
+            PqxError::InvalidHex(e) => Some(e),
+            #[cfg(feature = "to_string")]
+            PqxError::InvalidBase64(e) => Some(e),
+            #[cfg(feature = "keystore")]
+            PqxError::InvalidDer(e) => Some(e),
+            PqxError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+#[cfg(feature = "std")]
+impl From<std::io::Error> for PqxError {
+    fn from(e: std::io::Error) -> Self {
+        PqxError::Io(e)
+    }
+}
+#[cfg(feature = "keystore")]
+impl From<pkcs8::der::Error> for PqxError {
+    fn from(e: pkcs8::der::Error) -> Self {
+        PqxError::InvalidDer(e)
+    }
+}
+#[cfg(any(feature = "to_string", feature = "keystore"))]
+impl From<hex::FromHexError> for PqxError {
+    fn from(e: hex::FromHexError) -> Self {
+        PqxError::InvalidHex(e)
+    }
+}
+#[cfg(feature = "to_string")]
+impl From<base64::DecodeError> for PqxError {
+    fn from(e: base64::DecodeError) -> Self {
+        PqxError::InvalidBase64(e)
+    }
 }
 /// Generation of the shared key
 #[derive(Clone, Debug, ZeroizeOnDrop, PartialEq, Eq)]
 pub struct Combinedshared {
-    shared: Box<[u8; Combinedshared::SHAREDLEN]>,
+    shared: [u8; Combinedshared::SHAREDLEN],
+    len: usize,
 }
-/// Final shared key after rounds of sha256
+/// Final shared key obtained by hashing or deriving the shared secret, its length is exactly the one requested
 #[derive(Clone, Debug, ZeroizeOnDrop, PartialEq, Eq)]
 pub struct Finalkey {
     shared: Vec<u8>,
 }
-/// The combinated key containing private key for X25519 and Kyber, should not be transferred
-pub struct Combinedkey {
-    kyber: safe_pqc_kyber::Keypair,
-    x25519: EphemeralSecret,
+/// Pseudorandom key obtained after the HKDF-SHA256 extract step, used to expand as many keys as needed
+#[derive(Clone, Debug, ZeroizeOnDrop, PartialEq, Eq)]
+pub struct Extractedkey {
+    prk: [u8; Extractedkey::PRKLEN],
+}
+/// The combinated key containing private keys of the KEM and of the Diffie-Hellman group, should not be transferred
+pub struct Hybridkey<K: Kem, D: DhGroup> {
+    kem: K::Keypair,
+    dh: D::Secret,
+    persistent: bool,
 }
-/// The combination key containing public key for X25519 and Kyber.
+/// The combination key containing public keys of the KEM and of the Diffie-Hellman group.
 #[derive(Clone, Debug, ZeroizeOnDrop, PartialEq, Eq)]
-pub struct Combinedpub {
-    pub kyber: [u8; KYBER_PUBLICKEYBYTES],
-    pub x25519: x25519_dalek::PublicKey,
+pub struct Hybridpub<K: Kem, D: DhGroup> {
+    kem: K::Public,
+    dh: D::Public,
 }
 /// The combinated shared to share between persons to obtain the secret key as well as the shared_key on server side
 #[derive(Clone, Debug, ZeroizeOnDrop, PartialEq, Eq)]
-pub struct Combinedcipher {
-    pub cipher: [u8; Combinedcipher::KEYSIZE],
-    shared_secret: Option<[u8; KYBER_SSBYTES]>,
+pub struct Hybridcipher<K: Kem, D: DhGroup> {
+    #[zeroize(skip)]
+    level: K::Level,
+    cipher: [u8; MAXCIPHERBYTES],
+    len: usize,
+    shared_secret: Option<K::Shared>,
+    #[zeroize(skip)]
+    group: PhantomData<D>,
 }
-impl Default for Combinedkey {
+/// Size of the biggest hybrid cipher, the KEM cipher followed by the Diffie-Hellman public key of the sender must fit in it.
+/// It holds a Kyber-1024 cipher with any common Diffie-Hellman public key (133 bytes for an uncompressed P-521 point).
+pub const MAXCIPHERBYTES: usize = 2048;
+/// Hybrid key with Kyber and X25519
+pub type Combinedkey = Hybridkey<Kyber, X25519>;
+/// Hybrid public key with Kyber and X25519
+pub type Combinedpub = Hybridpub<Kyber, X25519>;
+/// Hybrid cipher with Kyber and X25519
+pub type Combinedcipher = Hybridcipher<Kyber, X25519>;
+#[cfg(feature = "std")]
+impl<K: Kem, D: DhGroup> Default for Hybridkey<K, D> {
     /// Create a random-secure key for both algorithms
     fn default() -> Self {
-        let mut rng = thread_rng();
-        let alice_secret = EphemeralSecret::random_from_rng(&mut rng);
-        let kyber = safe_pqc_kyber::keypair(&mut rng);
-        Combinedkey {
-            kyber,
-            x25519: alice_secret,
-        }
+        Self::generate(K::Level::default(), false)
     }
 }
-#[cfg(feature = "keystore")]
-impl TryFrom<[u8; KYBER_PUBLICKEYBYTES + KYBER_SECRETKEYBYTES]> for Combinedkey {
-    type Error = PqxError;
-    //Extraction from public key then secret key
-    fn try_from(
-        data: [u8; KYBER_PUBLICKEYBYTES + KYBER_SECRETKEYBYTES],
-    ) -> Result<Self, Self::Error> {
-        let (public, secret) = (
-            data[..KYBER_PUBLICKEYBYTES].try_into(),
-            data[KYBER_PUBLICKEYBYTES..].try_into(),
-        );
-        let (public, secret) = match (public, secret) {
-            (Ok(a), Ok(b)) => (a, b),
-            _ => return Err(PqxError::InvalidInput),
-        };
-        let mut public: [u8; KYBER_PUBLICKEYBYTES] = public;
-        let mut secret: [u8; KYBER_SECRETKEYBYTES] = secret;
-        let mut rng = rand::thread_rng();
-        //Try to encapsulate and decapsule to verify secret key matches public key
-        let (ciphertext, shared_secret) = match encapsulate(&public, &mut rng) {
-            Ok(a) => a,
-            _ => return Err(PqxError::InvalidInput),
-        };
-        let expected_shared_secret = match decapsulate(&ciphertext, &secret) {
-            Ok(a) => a,
-            _ => return Err(PqxError::InvalidInput),
+impl<K: Kem, D: DhGroup> Hybridkey<K, D> {
+    /// Both shared secrets must fit in a [`Combinedshared`] to be concatenated
+    const FITS: () = assert!(K::SSBYTES + D::SSBYTES <= Combinedshared::SHAREDLEN);
+    /// Create a random-secure key for both algorithms
+    #[cfg(feature = "std")]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Create a random-secure key for both algorithms whose Diffie-Hellman secret is static,
+    /// so that it can be stored and reused as a long-term identity (see [`key::printcombinedkeystofile`])
+    #[cfg(feature = "std")]
+    pub fn new_static() -> Self {
+        Self::generate(K::Level::default(), true)
+    }
+    /// Create a random-secure key with the given KEM level, with a static Diffie-Hellman secret if `persistent` is set
+    #[cfg(feature = "std")]
+    pub fn generate(level: K::Level, persistent: bool) -> Self {
+        Self::generate_with_rng(level, persistent, &mut thread_rng())
+    }
+    /// Create a key with the given KEM level from the given random generator
+    pub fn generate_with_rng<R: RngCore + CryptoRng>(
+        level: K::Level,
+        persistent: bool,
+        rng: &mut R,
+    ) -> Self {
+        Hybridkey {
+            kem: K::generate(level, rng),
+            dh: D::generate(rng),
+            persistent,
+        }
+    }
+    /// Create a key from a KEM keypair and a static Diffie-Hellman secret.
+    /// Without Diffie-Hellman secret, a new ephemeral one is generated.
+    #[cfg(feature = "std")]
+    pub fn fromsecrets(kem: K::Keypair, dh: Option<D::Secret>) -> Self {
+        Self::fromsecrets_with_rng(kem, dh, &mut thread_rng())
+    }
+    /// Create a key from a KEM keypair and a static Diffie-Hellman secret,
+    /// the ephemeral secret being generated from the given random generator if needed
+    pub fn fromsecrets_with_rng<R: RngCore + CryptoRng>(
+        kem: K::Keypair,
+        dh: Option<D::Secret>,
+        rng: &mut R,
+    ) -> Self {
+        let persistent = dh.is_some();
+        let dh = match dh {
+            Some(secret) => secret,
+            None => D::generate(rng),
         };
-        //If it does match, return a KeyPair
-        if expected_shared_secret == shared_secret {
-            let key = Keypair { public, secret };
-            public.zeroize();
-            secret.zeroize();
-            let alice_secret = EphemeralSecret::random_from_rng(&mut rng);
-            Ok(Combinedkey {
-                kyber: key,
-                x25519: alice_secret,
-            })
-        } else {
-            //Else return an error
-            Err(PqxError::InvalidInput)
+        Hybridkey {
+            kem,
+            dh,
+            persistent,
         }
     }
+    /// Check if the Diffie-Hellman secret is static and can be stored
+    pub fn isstatic(&self) -> bool {
+        self.persistent
+    }
+    /// KEM level of the key
+    pub fn getlevel(&self) -> K::Level {
+        K::level(&self.kem)
+    }
+    /// Check kyber keys comparaison
+    pub fn checkkeys(&self, other: &Self) -> bool {
+        self.kem == other.kem
+    }
 }
 impl Combinedkey {
-    /// Create a random-secure key for both algorithms
-    pub fn new() -> Self {
-        Self::default()
+    /// Derive a key with the given Kyber level from a secret seed of 32 or 64 bytes, so that only the seed needs to be kept.
+    /// A 32-byte seed is expanded with SHAKE256 into the Kyber seed `d || z` then the X25519 secret:
+    /// with ML-KEM-768, this is the key generation of X-Wing.
+    /// A 64-byte seed is the Kyber seed `d || z` itself, the X25519 secret being the first 32 bytes of its SHAKE256.
+    /// The X25519 secret is static.
+    /// ```rust
+    /// use pqx::*;
+    /// let seed = [7u8; 32];
+    /// let key = Combinedkey::fromseed(KyberLevel::Kyber768, &seed).unwrap();
+    /// assert!(key.isstatic());
+    /// assert!(Combinedpub::new(&key) == Combinedpub::new(&Combinedkey::fromseed(KyberLevel::Kyber768, &seed).unwrap()));
+    /// ```
+    pub fn fromseed(level: KyberLevel, seed: &[u8]) -> Result<Self, PqxError> {
+        let mut kyberseed = Zeroizing::new([0u8; KYBER_SEEDBYTES]);
+        let mut x25519 = Zeroizing::new([0u8; Combinedpub::KEYSIZE]);
+        let mut shake = Shake256::default();
+        sha3::digest::Update::update(&mut shake, seed);
+        let mut reader = shake.finalize_xof();
+        match seed.len() {
+            32 => reader.read(kyberseed.as_mut()),
+            64 => kyberseed.copy_from_slice(seed),
+            _ => return Err(PqxError::InvalidLength(seed.len())),
+        }
+        reader.read(x25519.as_mut());
+        Ok(Hybridkey {
+            kem: Kyberkeypair::fromseed(level, &kyberseed),
+            dh: StaticSecret::from(*x25519),
+            persistent: true,
+        })
+    }
+    /// Create a key from a Kyber keypair and a static X25519 secret.
+    /// Without X25519 secret, a new ephemeral one is generated.
+    #[cfg(feature = "std")]
+    pub fn fromkeys(kyber: Kyberkeypair, x25519: Option<[u8; Combinedpub::KEYSIZE]>) -> Self {
+        Self::fromkeys_with_rng(kyber, x25519, &mut thread_rng())
+    }
+    /// Create a key from a Kyber keypair and a static X25519 secret,
+    /// the ephemeral secret being generated from the given random generator if needed
+    pub fn fromkeys_with_rng<R: RngCore + CryptoRng>(
+        kyber: Kyberkeypair,
+        x25519: Option<[u8; Combinedpub::KEYSIZE]>,
+        rng: &mut R,
+    ) -> Self {
+        let x25519 = x25519.map(|mut secret| {
+            let result = StaticSecret::from(secret);
+            secret.zeroize();
+            result
+        });
+        Self::fromsecrets_with_rng(kyber, x25519, rng)
     }
     /// Display public or private key
     #[cfg(feature = "keystore")]
     pub fn displaykyberkey(&self, private: bool) -> &[u8] {
         match private {
-            false => &self.kyber.public,
-            true => &self.kyber.secret
+            false => self.kem.getpublic(),
+            true => self.kem.getsecret(),
         }
     }
-    /// Check kyber keys comparaison
-    pub fn checkkeys(&self, other: &Self) -> bool {
-        self.kyber == other.kyber
+    /// Display the static X25519 secret key, `None` if the secret is ephemeral
+    #[cfg(feature = "keystore")]
+    pub fn displayx25519key(&self) -> Option<[u8; Combinedpub::KEYSIZE]> {
+        match self.persistent {
+            false => None,
+            true => Some(self.dh.to_bytes()),
+        }
     }
     /// Display public or private key
     #[cfg(feature = "keystore")]
-    pub fn getkyberkeypair(&self) -> &Keypair {
-        &self.kyber
+    pub fn getkyberkeypair(&self) -> &Kyberkeypair {
+        &self.kem
     }
 }
 impl Finalkey {
@@ -152,172 +379,596 @@ impl Finalkey {
     pub fn get(&self) -> &[u8] {
         &self.shared
     }
+    /// Length of the key in bytes
+    pub fn len(&self) -> usize {
+        self.shared.len()
+    }
+    /// Length of the key in bits
+    pub fn bits(&self) -> usize {
+        self.shared.len() * 8
+    }
+    /// Check if the key is empty (only possible when deriving a key of length 0)
+    pub fn is_empty(&self) -> bool {
+        self.shared.is_empty()
+    }
+}
+impl Extractedkey {
+    /// Length of the pseudorandom key (output size of SHA-256)
+    const PRKLEN: usize = 32;
+    /// Maximum length that can be expanded from a single pseudorandom key (255 blocks of SHA-256)
+    pub const MAXLEN: usize = 255 * Self::PRKLEN;
+    /// Expand a key of `length` bytes bound to the `info` context (HKDF-Expand from RFC 5869)
+    pub fn expand(&self, info: &[u8], length: usize) -> Result<Finalkey, PqxError> {
+        if length > Self::MAXLEN {
+            return Err(PqxError::InvalidLength(length));
+        }
+        let mut shared = vec![0u8; length];
+        match self.expand_into(info, &mut shared) {
+            Ok(()) => Ok(Finalkey { shared }),
+            Err(e) => {
+                shared.zeroize();
+                Err(e)
+            }
+        }
+    }
+    /// Expand a key bound to the `info` context directly into `output`, filling it completely
+    pub fn expand_into(&self, info: &[u8], output: &mut [u8]) -> Result<(), PqxError> {
+        let hkdf = match Hkdf::<Sha256>::from_prk(&self.prk) {
+            Ok(hkdf) => hkdf,
+            Err(_) => return Err(PqxError::InvalidInput),
+        };
+        match hkdf.expand(info, output) {
+            Ok(()) => Ok(()),
+            Err(_) => Err(PqxError::InvalidLength(output.len())),
+        }
+    }
 }
 /// Display the key in an hexadecimal format `a0a0a0a0`
 #[cfg(feature = "to_string")]
 impl Display for Finalkey {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "{}", hex::encode(&self.shared))
     }
 }
+impl<K: Kem, D: DhGroup> Hybridpub<K, D> {
+    /// Create public keys from private keys
+    pub fn new(key: &Hybridkey<K, D>) -> Self {
+        Hybridpub {
+            kem: K::public(&key.kem),
+            dh: D::public(&key.dh),
+        }
+    }
+    /// Create public keys from a KEM public key of the given level (or of the level given by its size)
+    /// and a Diffie-Hellman public key. Both keys are validated.
+    pub fn fromparts(level: Option<K::Level>, kem: &[u8], dh: &[u8]) -> Result<Self, PqxError> {
+        let dh = D::publicfrombytes(dh)?;
+        if !D::checkpublic(&dh) {
+            return Err(PqxError::InvalidPublicKey);
+        }
+        Ok(Hybridpub {
+            kem: K::publicfrombytes(level, kem)?,
+            dh,
+        })
+    }
+    /// KEM level of the public key
+    pub fn getlevel(&self) -> K::Level {
+        K::publiclevel(&self.kem)
+    }
+    /// KEM public key
+    pub fn getkem(&self) -> &[u8] {
+        self.kem.as_ref()
+    }
+    /// Diffie-Hellman public key
+    pub fn getdh(&self) -> &D::Public {
+        &self.dh
+    }
+}
 impl Combinedpub {
     const KEYSIZE: usize = x25519_dalek::X25519_BASEPOINT_BYTES.as_slice().len();
-    /// Create public keys from private keys
-    pub fn new(key: &Combinedkey) -> Self {
-        let kyber = key.kyber.public;
-        let x25519 = x25519_dalek::PublicKey::from(&key.x25519);
-        Combinedpub { kyber, x25519 }
+    /// Version of the encoding of [`Combinedpub::to_bytes`]
+    pub const VERSION: u8 = 1;
+    /// Identifier of X25519 in the encoding of [`Combinedpub::to_bytes`]
+    const X25519ID: u8 = 1;
+    /// Length of the header of [`Combinedpub::to_bytes`]
+    const HEADERLEN: usize = 4;
+    /// Create public keys from a round 3 Kyber public key (its level is given by its size) and a X25519 public key
+    pub fn fromkeys(kyber: &[u8], x25519: [u8; Self::KEYSIZE]) -> Result<Self, PqxError> {
+        Self::fromparts(None, kyber, &x25519)
+    }
+    /// Create public keys from a Kyber public key of the given level and a X25519 public key
+    pub fn withlevel(
+        level: KyberLevel,
+        kyber: &[u8],
+        x25519: [u8; Self::KEYSIZE],
+    ) -> Result<Self, PqxError> {
+        Self::fromparts(Some(level), kyber, &x25519)
+    }
+    /// Kyber public key
+    pub fn getkyber(&self) -> &[u8] {
+        self.getkem()
+    }
+    /// Encode the public key to be sent to network, half the size of the string form.
+    /// A 4 bytes header gives the version, the KEM (1 for round 3 Kyber, 2 for ML-KEM), the Diffie-Hellman group
+    /// (1 for X25519) and the NIST security category of the level (1, 3 or 5), followed by both public keys.
+    /// ```rust
+    /// use pqx::*;
+    /// let key = Combinedkey::generate(KyberLevel::Kyber1024, false);
+    /// let bytes = Combinedpub::new(&key).to_bytes();
+    /// assert_eq!(bytes[..4], [Combinedpub::VERSION, 1, 1, 5]);
+    /// assert!(Combinedpub::from_bytes(&bytes).unwrap() == Combinedpub::new(&key));
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let (kem, category) = self.getlevel().wireid();
+        let mut bytes = Vec::with_capacity(Self::HEADERLEN + self.getkem().len() + Self::KEYSIZE);
+        bytes.extend_from_slice(&[Self::VERSION, kem, Self::X25519ID, category]);
+        bytes.extend_from_slice(self.getkem());
+        bytes.extend_from_slice(self.dh.as_bytes());
+        bytes
+    }
+    /// Decode a public key encoded by [`Combinedpub::to_bytes`].
+    /// An unknown version or algorithm gives [`PqxError::UnsupportedVersion`] or [`PqxError::UnsupportedAlgorithm`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PqxError> {
+        if bytes.len() < Self::HEADERLEN {
+            return Err(PqxError::InvalidLength(bytes.len()));
+        }
+        if bytes[0] != Self::VERSION {
+            return Err(PqxError::UnsupportedVersion(bytes[0]));
+        }
+        let level = match (KyberLevel::fromwireid(bytes[1], bytes[3]), bytes[2]) {
+            (Some(level), Self::X25519ID) => level,
+            _ => return Err(PqxError::UnsupportedAlgorithm),
+        };
+        let keys = &bytes[Self::HEADERLEN..];
+        if keys.len() != level.publickeybytes() + Self::KEYSIZE {
+            return Err(PqxError::InvalidLength(bytes.len()));
+        }
+        let (kyber, x25519) = keys.split_at(level.publickeybytes());
+        Self::fromparts(Some(level), kyber, x25519)
     }
 }
-/// Give public key as string to be sent to network
+/// Give public key as string to be sent to network, `kyber768|kyberhex|x25519hex`
 #[cfg(feature = "to_string")]
-impl Display for Combinedpub {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl<K: Kem, D: DhGroup> Display for Hybridpub<K, D> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(
             f,
-            "{}|{}",
-            hex::encode(self.kyber),
-            hex::encode(self.x25519.as_bytes())
+            "{}|{}|{}",
+            self.getlevel().name(),
+            hex::encode(self.getkem()),
+            hex::encode(self.dh.as_ref())
         )
     }
 }
 /// Create public key from info transmitted from network
 #[cfg(feature = "to_string")]
-impl TryFrom<&str> for Combinedpub {
+impl<K: Kem, D: DhGroup> TryFrom<&str> for Hybridpub<K, D> {
     type Error = PqxError;
 
     fn try_from(info: &str) -> Result<Self, Self::Error> {
-        let (kyber, x25519) = match info.split_once('|') {
+        let (kem, dh) = match info.rsplit_once('|') {
             None => return Err(PqxError::InvalidInput),
             Some(a) => a,
         };
-        let (kyber, x25519) = match (hex::decode(kyber), hex::decode(x25519)) {
-            (Ok(a), Ok(b)) => (a, b),
-            _ => return Err(PqxError::InvalidInput),
+        //Older versions did not give the level, it is then deduced from the size of the key
+        let (level, kem) = match kem.split_once('|') {
+            None => (None, kem),
+            Some((level, kem)) => match K::Level::fromname(level) {
+                Some(level) => (Some(level), kem),
+                None => return Err(PqxError::InvalidInput),
+            },
         };
-        if kyber.len() != KYBER_PUBLICKEYBYTES {
-            return Err(PqxError::InvalidInput);
+        Self::fromparts(level, &hex::decode(kem)?, &hex::decode(dh)?)
+    }
+}
+impl<K: Kem, D: DhGroup> Hybridcipher<K, D> {
+    /// Generate the cipher from private key of server, public key of client to be sent to client.
+    #[cfg(feature = "std")]
+    pub fn new(key: &Hybridkey<K, D>, pubkey: &Hybridpub<K, D>) -> Result<Self, PqxError> {
+        Self::new_with_rng(key, pubkey, &mut thread_rng())
+    }
+    /// Generate the cipher to be sent to client, encapsulating with the given random generator
+    pub fn new_with_rng<R: RngCore + CryptoRng>(
+        key: &Hybridkey<K, D>,
+        pubkey: &Hybridpub<K, D>,
+        rng: &mut R,
+    ) -> Result<Self, PqxError> {
+        let (result, _) = Self::fromparts(pubkey, &D::public(&key.dh), rng)?;
+        Ok(result)
+    }
+    /// Encapsulate a secret for `pubkey` and assemble the cipher with the Diffie-Hellman public key of the sender
+    fn fromparts<R: RngCore + CryptoRng>(
+        pubkey: &Hybridpub<K, D>,
+        dh: &D::Public,
+        rng: &mut R,
+    ) -> Result<(Self, K::Shared), PqxError> {
+        let level = pubkey.getlevel();
+        let len = level.ciphertextbytes();
+        if len + D::PUBLICBYTES > MAXCIPHERBYTES {
+            return Err(PqxError::InvalidLength(len + D::PUBLICBYTES));
         }
-        let kyber = match kyber[..KYBER_PUBLICKEYBYTES].try_into() {
-            Ok(s) => s,
-            _ => return Err(PqxError::InvalidInput),
+        let mut cipher = [0u8; MAXCIPHERBYTES];
+        let shared = match K::encapsulate(&pubkey.kem, &mut cipher[..len], rng) {
+            Ok(shared) => shared,
+            Err(_) => return Err(PqxError::KyberError),
         };
-        if x25519.len() != Self::KEYSIZE {
-            return Err(PqxError::InvalidInput);
-        }
-        let x25519: [u8; Self::KEYSIZE] = match x25519[..Self::KEYSIZE].try_into() {
-            Ok(s) => s,
-            _ => return Err(PqxError::InvalidInput),
+        cipher[len..len + D::PUBLICBYTES].copy_from_slice(dh.as_ref());
+        let result = Hybridcipher {
+            level,
+            cipher,
+            len: len + D::PUBLICBYTES,
+            shared_secret: Some(shared.clone()),
+            group: PhantomData,
         };
-        let x25519 = x25519_dalek::PublicKey::from(x25519);
-        Ok(Combinedpub { kyber, x25519 })
+        Ok((result, shared))
+    }
+    /// Encapsulate a fresh shared secret for the owner of `pubkey`, without needing a [`Combinedkey`].
+    /// Only an ephemeral Diffie-Hellman secret is generated; the receiver gets the same secret with [`Combinedshared::new`].
+    /// ```rust
+    /// use pqx::*;
+    /// let bob_s = Combinedkey::default();
+    /// let bob_p = Combinedpub::new(&bob_s);
+    /// let (cipher, alice) = Combinedcipher::encapsulate(&bob_p).unwrap();
+    /// let bob = Combinedshared::new(bob_s, Combinedcipher::try_from(cipher.getcipher()).unwrap()).unwrap();
+    /// assert!(alice.getshared(SHAREDSIZE::High) == bob.getshared(SHAREDSIZE::High));
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn encapsulate(pubkey: &Hybridpub<K, D>) -> Result<(Self, Combinedshared), PqxError> {
+        Self::encapsulate_with(pubkey, Combiner::Concat)
+    }
+    /// Encapsulate a fresh shared secret for the owner of `pubkey` with the given combiner
+    #[cfg(feature = "std")]
+    pub fn encapsulate_with(
+        pubkey: &Hybridpub<K, D>,
+        combiner: Combiner,
+    ) -> Result<(Self, Combinedshared), PqxError> {
+        Self::encapsulate_with_rng(pubkey, combiner, &mut thread_rng())
+    }
+    /// Encapsulate a fresh shared secret for the owner of `pubkey` with the given combiner and random generator.
+    /// A seeded generator gives reproducible ciphers for known-answer tests, it must never be used otherwise.
+    pub fn encapsulate_with_rng<R: RngCore + CryptoRng>(
+        pubkey: &Hybridpub<K, D>,
+        combiner: Combiner,
+        rng: &mut R,
+    ) -> Result<(Self, Combinedshared), PqxError> {
+        let mut ephemeral = D::generate(rng);
+        let (mut result, shared) = Self::fromparts(pubkey, &D::public(&ephemeral), rng)?;
+        result.shared_secret = None;
+        let diffie = agree::<D>(&ephemeral, &pubkey.dh);
+        ephemeral.zeroize();
+        let diffie = diffie?;
+        let combined = Combinedshared::combine(
+            combiner,
+            shared.as_ref(),
+            diffie.as_ref(),
+            result.getcipher(),
+            pubkey,
+        );
+        Ok((result, combined))
+    }
+    /// Get the cipher to be sent to the peer
+    pub fn getcipher(&self) -> &[u8] {
+        &self.cipher[..self.len]
+    }
+    /// KEM level of the cipher
+    pub fn getlevel(&self) -> K::Level {
+        self.level
+    }
+    /// Cipher as unpadded base64url, to be sent in URLs or text protocols
+    #[cfg(feature = "to_string")]
+    pub fn tobase64url(&self) -> String {
+        URL_SAFE_NO_PAD.encode(self.getcipher())
+    }
+    /// KEM cipher
+    fn getkem(&self) -> &[u8] {
+        &self.cipher[..self.level.ciphertextbytes()]
+    }
+    /// Diffie-Hellman public key of the sender
+    fn getdh(&self) -> Result<D::Public, PqxError> {
+        D::publicfrombytes(&self.getcipher()[self.level.ciphertextbytes()..])
     }
 }
-impl Combinedcipher {
-    /// Length of the cipher
-    const KEYSIZE: usize = KYBER_CIPHERTEXTBYTES + X25519_BASEPOINT_BYTES.as_slice().len();
-    /// Generate the cipher from private key of server, public key of client to be sent to client.
-    pub fn new(key: &Combinedkey, pubkey: &Combinedpub) -> Result<Combinedcipher, PqxError> {
-        let mut rng = thread_rng();
-        let (cipher, shared) = match encapsulate(&pubkey.kyber, &mut rng) {
-            Ok(data) => data,
-            Err(_) => return Err(PqxError::KyberError),
+impl<K: Kem, D: DhGroup> TryFrom<&[u8]> for Hybridcipher<K, D> {
+    type Error = PqxError;
+    /// Allow to create the cipher from the value obtained from the server, the level is given by its size.
+    /// ML-KEM ciphers have the same sizes as round 3 Kyber ones, the level is then fixed by the key when decapsulating.
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let level = match value.len().checked_sub(D::PUBLICBYTES) {
+            Some(len) if value.len() <= MAXCIPHERBYTES => match K::Level::fromciphertextlen(len) {
+                Some(level) => level,
+                None => return Err(PqxError::InvalidLength(value.len())),
+            },
+            _ => return Err(PqxError::InvalidLength(value.len())),
         };
-        let mut result = [0u8; Self::KEYSIZE];
-        result[..cipher.len()].copy_from_slice(&cipher);
-        result[cipher.len()..]
-            .copy_from_slice(x25519_dalek::PublicKey::from(&key.x25519).as_bytes());
-        Ok(Combinedcipher {
-            cipher: result,
-            shared_secret: Some(shared),
+        let mut cipher = [0u8; MAXCIPHERBYTES];
+        cipher[..value.len()].copy_from_slice(value);
+        Ok(Hybridcipher {
+            level,
+            cipher,
+            len: value.len(),
+            shared_secret: None,
+            group: PhantomData,
         })
     }
-    /// Get the cipher outside the structure (copy)
-    pub fn getcipher(&self) -> [u8; Self::KEYSIZE] {
-        self.cipher
+}
+/// Give the cipher as hexadecimal string to be sent to network
+#[cfg(feature = "to_string")]
+impl<K: Kem, D: DhGroup> Display for Hybridcipher<K, D> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", hex::encode(self.getcipher()))
     }
 }
-impl From<[u8; Combinedcipher::KEYSIZE]> for Combinedcipher {
-    /// Allow to create the cipher from the value obtained from the server
-    fn from(value: [u8; Combinedcipher::KEYSIZE]) -> Self {
-        Combinedcipher {
-            cipher: value,
-            shared_secret: None,
-        }
+/// Create the cipher from a hexadecimal string or from an unpadded base64url one (see [`Hybridcipher::tobase64url`]).
+/// The encoded sizes of valid ciphers never overlap: a string with the base64url size of a valid cipher is base64url,
+/// even if it is only made of hexadecimal digits, other strings of hexadecimal digits are hexadecimal.
+/// The level is given by the size of the cipher.
+#[cfg(feature = "to_string")]
+impl<K: Kem, D: DhGroup> TryFrom<&str> for Hybridcipher<K, D> {
+    type Error = PqxError;
+
+    fn try_from(info: &str) -> Result<Self, Self::Error> {
+        //Unpadded base64url gives 4 characters for 3 bytes, and 2 or 3 for the last 1 or 2 bytes
+        let len = info.len() * 3 / 4;
+        let isbase64 = (4 * len).div_ceil(3) == info.len()
+            && len
+                .checked_sub(D::PUBLICBYTES)
+                .and_then(K::Level::fromciphertextlen)
+                .is_some();
+        let ishex = !isbase64 && info.bytes().all(|c| c.is_ascii_hexdigit());
+        let cipher = match ishex {
+            true => hex::decode(info)?,
+            false => URL_SAFE_NO_PAD.decode(info)?,
+        };
+        Self::try_from(cipher.as_slice())
     }
 }
-/// Size of shared key wanted
+/// Way the Kyber and X25519 secrets are combined into the shared secret. Both parties must use the same combiner.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Combiner {
+    /// Concatenation of both secrets `kyber || x25519`, the historical behaviour of this crate
+    #[default]
+    Concat,
+    /// X-Wing combiner: `SHA3-256(kyber || x25519 || x25519 cipher || x25519 receiver public key || label)`.
+    /// The Kyber cipher is not hashed as the Kyber secret already depends on it.
+    /// Only with `KyberLevel::MlKem768` and X25519 is the exchange the X-Wing KEM: with round 3 Kyber, ML-KEM-1024
+    /// or another group, the combiner is the same but the result does not interoperate with X-Wing.
+    XWing,
+    /// Hash of the whole transcript: `SHA3-256(label || kyber || x25519 || cipher || receiver public key)`,
+    /// binding both ciphers and both public keys of the receiver.
+    Transcript,
+}
+impl Combiner {
+    /// Label of the X-Wing combiner (`\.//^\`)
+    const XWINGLABEL: &'static [u8] = b"\\.//^\\";
+    /// Domain separation label of the transcript combiner
+    const TRANSCRIPTLABEL: &'static [u8] = b"PQX-Kyber-X25519-transcript";
+    /// X-Wing combiner function from draft-connolly-cfrg-xwing-kem, computed from the KEM secret,
+    /// the X25519 secret, the ephemeral X25519 public key and the X25519 public key of the receiver
+    pub fn xwing(
+        kyber: &[u8; 32],
+        x25519: &[u8; 32],
+        cipher: &[u8; 32],
+        pubkey: &[u8; 32],
+    ) -> [u8; 32] {
+        Self::xwinghash(kyber, x25519, cipher, pubkey)
+    }
+    /// X-Wing combiner over secrets and keys of any size
+    fn xwinghash(kem: &[u8], dh: &[u8], cipher: &[u8], pubkey: &[u8]) -> [u8; 32] {
+        let mut sha = Sha3_256::new();
+        sha.update(kem);
+        sha.update(dh);
+        sha.update(cipher);
+        sha.update(pubkey);
+        sha.update(Self::XWINGLABEL);
+        sha.finalize().into()
+    }
+}
+/// Size of shared key wanted, the value of each variant is the length of the key in bits.
+/// For other lengths, use [`Combinedshared::derive`].
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum SHAREDSIZE {
+    /// 128-bit key (16 bytes), SHA-256 truncated
     Low = 128,
-    Med = 196,
+    /// 192-bit key (24 bytes), SHA-256 truncated
+    Med = 192,
+    /// 256-bit key (32 bytes), SHA-256
     High = 256,
+    /// 384-bit key (48 bytes), SHA-384
     VHigh = 384,
+    /// 512-bit key (64 bytes), SHA-512
     VVHigh = 512,
 }
+impl SHAREDSIZE {
+    /// Length of the key in bits
+    pub const fn bits(self) -> usize {
+        self as usize
+    }
+    /// Length of the key in bytes
+    pub const fn bytes(self) -> usize {
+        self as usize / 8
+    }
+}
 impl Combinedshared {
-    /// Length of the shared secret
-    const SHAREDLEN: usize = KYBER_SSBYTES + X25519_BASEPOINT_BYTES.as_slice().len();
+    /// Length of the biggest shared secret, both secrets of a hybrid key must fit in it
+    const SHAREDLEN: usize = 128;
     /// Create the shared secret from cipher (client side)
-    pub fn new(key: Combinedkey, cipher: Combinedcipher) -> Result<Self, PqxError> {
-        let (cipher, pubkey) = cipher.cipher.split_at(KYBER_CIPHERTEXTBYTES);
-        let pubkey: [u8; X25519_BASEPOINT_BYTES.as_slice().len()] =
-            match pubkey[..X25519_BASEPOINT_BYTES.as_slice().len()].try_into() {
-                Ok(pubkey) => pubkey,
-                Err(_) => return Err(PqxError::InvalidInput),
-            };
-        let pubkey = x25519_dalek::PublicKey::from(pubkey);
-        let diffie = key.x25519.diffie_hellman(&pubkey);
-        let shared_secret = match decapsulate(cipher, &key.kyber.secret) {
+    pub fn new<K: Kem, D: DhGroup>(
+        key: Hybridkey<K, D>,
+        cipher: Hybridcipher<K, D>,
+    ) -> Result<Self, PqxError> {
+        Self::new_with(key, cipher, Combiner::Concat)
+    }
+    /// Create the shared secret from cipher (client side) with the given combiner
+    pub fn new_with<K: Kem, D: DhGroup>(
+        key: Hybridkey<K, D>,
+        cipher: Hybridcipher<K, D>,
+        combiner: Combiner,
+    ) -> Result<Self, PqxError> {
+        Self::decapsulate(&key, &cipher, combiner)
+    }
+    /// Decapsulate the shared secret from cipher without consuming the key
+    fn decapsulate<K: Kem, D: DhGroup>(
+        key: &Hybridkey<K, D>,
+        cipher: &Hybridcipher<K, D>,
+        combiner: Combiner,
+    ) -> Result<Self, PqxError> {
+        //A received cipher only knows its size, the algorithm is given by the key
+        if cipher.level.ciphertextbytes() != key.getlevel().ciphertextbytes() {
+            return Err(PqxError::InvalidLength(cipher.getcipher().len()));
+        }
+        let shared_secret = match K::decapsulate(&key.kem, cipher.getkem()) {
             Ok(data) => data,
-            Err(_) => return Err(PqxError::InvalidInput),
+            Err(_) => return Err(PqxError::DecapsulationError),
         };
-        let mut combined: Vec<u8> = Vec::new();
-        combined.extend_from_slice(&shared_secret);
-        combined.extend_from_slice(diffie.as_bytes());
-        let elem = Ok(Combinedshared {
-            shared: Box::new(combined[..Self::SHAREDLEN].try_into().unwrap()),
-        });
-        combined.zeroize();
-        elem
+        let ownpub = Hybridpub::new(key);
+        let diffie = agree::<D>(&key.dh, &cipher.getdh()?)?;
+        Ok(Self::combine(
+            combiner,
+            shared_secret.as_ref(),
+            diffie.as_ref(),
+            cipher.getcipher(),
+            &ownpub,
+        ))
     }
     /// Retrieve the shared secret from generation (server side)
-    pub fn getfromshared(
-        shared: Combinedcipher,
-        pubkey: Combinedpub,
-        key: Combinedkey,
+    pub fn getfromshared<K: Kem, D: DhGroup>(
+        shared: Hybridcipher<K, D>,
+        pubkey: Hybridpub<K, D>,
+        key: Hybridkey<K, D>,
+    ) -> Result<Self, PqxError> {
+        Self::getfromshared_with(shared, pubkey, key, Combiner::Concat)
+    }
+    /// Retrieve the shared secret from generation (server side) with the given combiner.
+    /// The combiner must be the same as the one used by the peer.
+    pub fn getfromshared_with<K: Kem, D: DhGroup>(
+        shared: Hybridcipher<K, D>,
+        pubkey: Hybridpub<K, D>,
+        key: Hybridkey<K, D>,
+        combiner: Combiner,
     ) -> Result<Self, PqxError> {
-        let secret = match shared.shared_secret {
+        let secret = match &shared.shared_secret {
             Some(val) => val,
             None => return Err(PqxError::InvalidInput),
         };
-        let mut combined: Vec<u8> = Vec::new();
-        let diffie = key.x25519.diffie_hellman(&pubkey.x25519);
-        combined.extend_from_slice(&secret);
-        combined.extend_from_slice(diffie.as_bytes());
-        let elem = Ok(Combinedshared {
-            shared: Box::new(combined[..Combinedshared::SHAREDLEN].try_into().unwrap()),
-        });
-        combined.zeroize();
+        if shared.level != pubkey.getlevel() {
+            return Err(PqxError::KeyMismatch);
+        }
+        let diffie = agree::<D>(&key.dh, &pubkey.dh)?;
+        Ok(Self::combine(
+            combiner,
+            secret.as_ref(),
+            diffie.as_ref(),
+            shared.getcipher(),
+            &pubkey,
+        ))
+    }
+    /// Combine both secrets, the cipher and the public key of the receiver
+    fn combine<K: Kem, D: DhGroup>(
+        combiner: Combiner,
+        kem: &[u8],
+        dh: &[u8],
+        cipher: &[u8],
+        pubkey: &Hybridpub<K, D>,
+    ) -> Self {
+        let () = Hybridkey::<K, D>::FITS;
+        let mut elem = Combinedshared {
+            shared: [0u8; Self::SHAREDLEN],
+            len: 0,
+        };
+        match combiner {
+            Combiner::Concat => {
+                elem.shared[..kem.len()].copy_from_slice(kem);
+                elem.shared[kem.len()..kem.len() + dh.len()].copy_from_slice(dh);
+                elem.len = kem.len() + dh.len();
+            }
+            Combiner::XWing => {
+                let ciphertext = &cipher[pubkey.getlevel().ciphertextbytes()..];
+                let hash = Combiner::xwinghash(kem, dh, ciphertext, pubkey.dh.as_ref());
+                elem.shared[..hash.len()].copy_from_slice(&hash);
+                elem.len = hash.len();
+            }
+            Combiner::Transcript => {
+                let mut sha = Sha3_256::new();
+                sha.update(Combiner::TRANSCRIPTLABEL);
+                sha.update(kem);
+                sha.update(dh);
+                sha.update(cipher);
+                sha.update(pubkey.getkem());
+                sha.update(pubkey.dh.as_ref());
+                let hash = sha.finalize();
+                elem.shared[..hash.len()].copy_from_slice(&hash);
+                elem.len = hash.len();
+            }
+        }
         elem
     }
-    /// Get the wanted size of shared key (consumes the element)
+    /// Shared secret as computed by the combiner
+    fn secret(&self) -> &[u8] {
+        &self.shared[..self.len]
+    }
+    /// Get the wanted size of shared key (consumes the element), the key is exactly `size.bytes()` long
     pub fn getshared(self, size: SHAREDSIZE) -> Finalkey {
-        let element: Vec<u8> = match size {
-            SHAREDSIZE::Low => {
-                let mut sha = Sha256::digest(self.shared.as_ref()).to_vec();
-                sha.truncate(128);
-                sha
-            }
-            SHAREDSIZE::Med => {
-                let mut sha = Sha256::digest(self.shared.as_ref()).to_vec();
-                sha.truncate(196);
-                sha
+        let mut element: Vec<u8> = match size {
+            SHAREDSIZE::Low | SHAREDSIZE::Med | SHAREDSIZE::High => {
+                Sha256::digest(self.secret()).to_vec()
             }
-            SHAREDSIZE::High => Sha256::digest(self.shared.as_ref()).to_vec(),
-            SHAREDSIZE::VHigh => Sha384::digest(self.shared.as_ref()).to_vec(),
-            SHAREDSIZE::VVHigh => Sha512::digest(self.shared.as_ref()).to_vec(),
+            SHAREDSIZE::VHigh => Sha384::digest(self.secret()).to_vec(),
+            SHAREDSIZE::VVHigh => Sha512::digest(self.secret()).to_vec(),
         };
+        element[size.bytes()..].zeroize();
+        element.truncate(size.bytes());
         Finalkey { shared: element }
     }
+    /// Get the shared key as computed by older versions of this crate, where `Low`, `Med` and `High`
+    /// all gave the 32 bytes of SHA-256. Only use it to keep talking to peers that were not updated.
+    #[deprecated(note = "Low and Med do not give the requested length, use getshared instead")]
+    pub fn getshared_legacy(self, size: SHAREDSIZE) -> Finalkey {
+        match size {
+            SHAREDSIZE::Low | SHAREDSIZE::Med => self.getshared(SHAREDSIZE::High),
+            _ => self.getshared(size),
+        }
+    }
+    /// Run the HKDF-SHA256 extract step over the shared secret (RFC 5869).
+    /// Without salt, a string of zeros of hash length is used as specified by the RFC.
+    pub fn extract(&self, salt: Option<&[u8]>) -> Extractedkey {
+        let (prk, _) = Hkdf::<Sha256>::extract(salt, self.secret());
+        let mut result = Extractedkey {
+            prk: [0u8; Extractedkey::PRKLEN],
+        };
+        result.prk.copy_from_slice(&prk);
+        result
+    }
+    /// Derive a key of `length` bytes with HKDF-SHA256 using the given `salt` and `info`.
+    /// Different `info` labels give independent keys, so one exchange can feed several keys (encryption key, MAC key, IV...).
+    /// ```rust
+    /// use pqx::*;
+    /// let bob_s = Combinedkey::default();
+    /// let bob_p = Combinedpub::new(&bob_s);
+    /// let alice_s = Combinedkey::default();
+    /// let cipher = Combinedcipher::new(&alice_s, &bob_p).unwrap();
+    /// let received = Combinedcipher::try_from(cipher.getcipher()).unwrap();
+    /// let alice = Combinedshared::getfromshared(cipher, bob_p, alice_s).unwrap();
+    /// let bob = Combinedshared::new(bob_s, received).unwrap();
+    /// let aeskey = alice.derive(Some(b"session salt"), b"aes-256-gcm key", 32).unwrap();
+    /// let iv = alice.derive(Some(b"session salt"), b"aes-256-gcm iv", 12).unwrap();
+    /// assert_eq!(aeskey, bob.derive(Some(b"session salt"), b"aes-256-gcm key", 32).unwrap());
+    /// assert_eq!(iv.get().len(), 12);
+    /// assert_ne!(aeskey.get()[..12], iv.get()[..]);
+    /// ```
+    pub fn derive(
+        &self,
+        salt: Option<&[u8]>,
+        info: &[u8],
+        length: usize,
+    ) -> Result<Finalkey, PqxError> {
+        self.extract(salt).expand(info, length)
+    }
+    /// Derive a key with HKDF-SHA256 directly into `output`, filling it completely
+    pub fn derive_into(
+        &self,
+        salt: Option<&[u8]>,
+        info: &[u8],
+        output: &mut [u8],
+    ) -> Result<(), PqxError> {
+        self.extract(salt).expand_into(info, output)
+    }
 }
@@ -0,0 +1,157 @@
+//! Serde support. Binary formats get raw bytes and human-readable ones hexadecimal strings.
+//! Public keys and ciphers are always available, keys only with the `serde_secret` feature.
+use serde::de::{self, Deserializer, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+use zeroize::Zeroizing;
+
+#[cfg(feature = "serde_secret")]
+use crate::{Combinedkey, KyberLevel, Kyberkeypair};
+use crate::{
+    DhGroup, Hybridcipher, Hybridpub, Kem, Kemlevel, KYBER_MAXSECRETKEYBYTES, MAXCIPHERBYTES,
+};
+/// Bytes serialized as hexadecimal in human-readable formats
+struct Bytes<'a>(&'a [u8]);
+impl Serialize for Bytes<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match serializer.is_human_readable() {
+            true => serializer.serialize_str(&hex::encode(self.0)),
+            false => serializer.serialize_bytes(self.0),
+        }
+    }
+}
+/// Longest byte string accepted, a Kyber secret key or a cipher
+const MAXBYTES: usize = if KYBER_MAXSECRETKEYBYTES > MAXCIPHERBYTES {
+    KYBER_MAXSECRETKEYBYTES
+} else {
+    MAXCIPHERBYTES
+};
+/// Deserialized bytes, zeroized when dropped as they can be secret
+struct Bytesbuf(Zeroizing<Vec<u8>>);
+impl<'de> Deserialize<'de> for Bytesbuf {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Bytesvisitor;
+        impl<'de> Visitor<'de> for Bytesvisitor {
+            type Value = Bytesbuf;
+            fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str("bytes or a hexadecimal string")
+            }
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Bytesbuf, E> {
+                match hex::decode(value) {
+                    Ok(bytes) => Ok(Bytesbuf(Zeroizing::new(bytes))),
+                    Err(e) => Err(E::custom(e)),
+                }
+            }
+            fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<Bytesbuf, E> {
+                Ok(Bytesbuf(Zeroizing::new(value.to_vec())))
+            }
+            fn visit_byte_buf<E: de::Error>(self, value: Vec<u8>) -> Result<Bytesbuf, E> {
+                Ok(Bytesbuf(Zeroizing::new(value)))
+            }
+            //Some formats encode bytes as a sequence of integers, the declared length is not trusted
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Bytesbuf, A::Error> {
+                let capacity = seq.size_hint().unwrap_or(0).min(MAXBYTES);
+                let mut bytes = Zeroizing::new(Vec::with_capacity(capacity));
+                while let Some(byte) = seq.next_element()? {
+                    if bytes.len() == MAXBYTES {
+                        return Err(de::Error::invalid_length(bytes.len() + 1, &self));
+                    }
+                    bytes.push(byte);
+                }
+                Ok(Bytesbuf(bytes))
+            }
+        }
+        match deserializer.is_human_readable() {
+            true => deserializer.deserialize_str(Bytesvisitor),
+            false => deserializer.deserialize_bytes(Bytesvisitor),
+        }
+    }
+}
+/// Public key as `kyber768|kyberhex|x25519hex` in human-readable formats, as (level, KEM key, Diffie-Hellman key) otherwise
+impl<K: Kem, D: DhGroup> Serialize for Hybridpub<K, D> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match serializer.is_human_readable() {
+            true => serializer.collect_str(self),
+            false => (
+                self.getlevel().name(),
+                Bytes(self.getkem()),
+                Bytes(self.dh.as_ref()),
+            )
+                .serialize(serializer),
+        }
+    }
+}
+impl<'de, K: Kem, D: DhGroup> Deserialize<'de> for Hybridpub<K, D> {
+    fn deserialize<T: Deserializer<'de>>(deserializer: T) -> Result<Self, T::Error> {
+        match deserializer.is_human_readable() {
+            true => {
+                let text = String::deserialize(deserializer)?;
+                Self::try_from(text.as_str()).map_err(de::Error::custom)
+            }
+            false => {
+                let (level, kem, dh) = <(String, Bytesbuf, Bytesbuf)>::deserialize(deserializer)?;
+                let level = match K::Level::fromname(&level) {
+                    Some(level) => level,
+                    None => return Err(de::Error::custom("unknown level")),
+                };
+                Self::fromparts(Some(level), &kem.0, &dh.0).map_err(de::Error::custom)
+            }
+        }
+    }
+}
+/// Cipher as sent to the peer, the level is given by its size
+impl<K: Kem, D: DhGroup> Serialize for Hybridcipher<K, D> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Bytes(self.getcipher()).serialize(serializer)
+    }
+}
+/// Human-readable formats also accept the base64url form of the cipher
+impl<'de, K: Kem, D: DhGroup> Deserialize<'de> for Hybridcipher<K, D> {
+    fn deserialize<T: Deserializer<'de>>(deserializer: T) -> Result<Self, T::Error> {
+        match deserializer.is_human_readable() {
+            true => {
+                let text = String::deserialize(deserializer)?;
+                Self::try_from(text.as_str()).map_err(de::Error::custom)
+            }
+            false => {
+                let cipher = Bytesbuf::deserialize(deserializer)?;
+                Self::try_from(cipher.0.as_slice()).map_err(de::Error::custom)
+            }
+        }
+    }
+}
+/// Key as (level, Kyber secret key, static X25519 secret key if any). Keep the output safe.
+#[cfg(feature = "serde_secret")]
+impl Serialize for Combinedkey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let x25519 = match self.persistent {
+            true => Some(Zeroizing::new(self.dh.to_bytes())),
+            false => None,
+        };
+        (
+            self.getlevel().name(),
+            Bytes(self.kem.getsecret()),
+            x25519.as_ref().map(|secret| Bytes(secret.as_slice())),
+        )
+            .serialize(serializer)
+    }
+}
+#[cfg(feature = "serde_secret")]
+impl<'de> Deserialize<'de> for Combinedkey {
+    fn deserialize<T: Deserializer<'de>>(deserializer: T) -> Result<Self, T::Error> {
+        let (level, kyber, x25519) =
+            <(String, Bytesbuf, Option<Bytesbuf>)>::deserialize(deserializer)?;
+        let level = match KyberLevel::fromname(&level) {
+            Some(level) => level,
+            None => return Err(de::Error::custom("unknown level")),
+        };
+        let kyber = Kyberkeypair::fromsecret(level, &kyber.0).map_err(de::Error::custom)?;
+        let x25519 = match x25519 {
+            Some(secret) => match secret.0.as_slice().try_into() {
+                Ok(secret) => Some(secret),
+                Err(_) => return Err(de::Error::invalid_length(secret.0.len(), &"32 bytes")),
+            },
+            None => None,
+        };
+        Ok(Combinedkey::fromkeys(kyber, x25519))
+    }
+}
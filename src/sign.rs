@@ -0,0 +1,530 @@
+//! Composite signatures made of ML-DSA (FIPS 204) and Ed25519, to sign the public keys given to peers.
+//! A signature is only valid if both components are, so it stands as long as one of the algorithms is unbroken.
+//! Both components are bound to this composite with a label, a component cannot be taken out as a signature of its own.
+//! Key files follow the conventions of the [`crate::key`] module: PEM around the generic composite key.
+//! The `sign` feature is not enabled by default: it needs Rust 1.85 or later (for `ml-dsa`), above the `rust-version`
+//! of the crate, and the `std` feature.
+//! ```rust
+//! use pqx::*;
+//! use pqx::sign::*;
+//! let signer = Signkey::generate(Signlevel::MlDsa65);
+//! let published = Combinedpub::new(&Combinedkey::new_static());
+//! let signature = signer.sign(&published.to_bytes()).unwrap();
+//! //The peer gets the public key of the signer beforehand
+//! let verifier = Signpub::from_bytes(&signer.getpublic().to_bytes()).unwrap();
+//! let signature = Signature::try_from(signature.getbytes()).unwrap();
+//! assert!(verifier.verify(&published.to_bytes(), &signature).is_ok());
+//! assert!(matches!(verifier.verify(b"other", &signature), Err(PqxError::InvalidSignature)));
+//! ```
+use alloc::vec::Vec;
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+use ml_dsa::{EncodedVerifyingKey, KeyGen, MlDsa44, MlDsa65, MlDsa87, B32};
+use rand::{CryptoRng, RngCore};
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+#[cfg(feature = "keystore")]
+use {
+    crate::key::{
+        algorithm, compositealgorithm, frompem, printtexts, readtext, writekeys, COMPOSITEOID,
+        PEM_LINE_ENDING, PRIVATEPEM, PUBLICPEM,
+    },
+    pkcs8::der::asn1::{BitStringRef, ObjectIdentifier, OctetStringRef},
+    pkcs8::der::{Decode, Document, Encode, SecretDocument},
+    pkcs8::spki::{AlgorithmIdentifierRef, SubjectPublicKeyInfoRef},
+    pkcs8::PrivateKeyInfo,
+    std::ffi::OsStr,
+    std::fs::File,
+    std::io::{Read, Write},
+    std::path::Path,
+};
+
+use crate::PqxError;
+/// Domain separation label, the context of ML-DSA and the prefix of the message signed by Ed25519
+const SIGNLABEL: &[u8] = b"PQX-MLDSA-Ed25519";
+/// Length of the seeds of both secret keys
+const SEEDBYTES: usize = 32;
+/// Length of Ed25519 public keys
+const ED25519PUBLICBYTES: usize = 32;
+/// Length of Ed25519 signatures
+const ED25519SIGNATUREBYTES: usize = 64;
+/// Ed25519 from RFC 8410
+#[cfg(feature = "keystore")]
+const ED25519OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.101.112");
+/// Run a block with the ML-DSA parameter set of a level
+macro_rules! mldsa {
+    ($level:expr, $params:ident => $body:block) => {
+        match $level {
+            Signlevel::MlDsa44 => {
+                type $params = MlDsa44;
+                $body
+            }
+            Signlevel::MlDsa65 => {
+                type $params = MlDsa65;
+                $body
+            }
+            Signlevel::MlDsa87 => {
+                type $params = MlDsa87;
+                $body
+            }
+        }
+    };
+}
+/// Security level of the ML-DSA component
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Signlevel {
+    /// ML-DSA-44, NIST security category 2
+    MlDsa44,
+    /// ML-DSA-65, NIST security category 3
+    #[default]
+    MlDsa65,
+    /// ML-DSA-87, NIST security category 5
+    MlDsa87,
+}
+impl Signlevel {
+    /// Every supported level
+    pub const ALL: &'static [Signlevel] =
+        &[Signlevel::MlDsa44, Signlevel::MlDsa65, Signlevel::MlDsa87];
+    /// Name of the level, as used in serialized forms
+    pub const fn name(self) -> &'static str {
+        match self {
+            Signlevel::MlDsa44 => "mldsa44-ed25519",
+            Signlevel::MlDsa65 => "mldsa65-ed25519",
+            Signlevel::MlDsa87 => "mldsa87-ed25519",
+        }
+    }
+    /// Get the level from its name (case insensitive)
+    pub fn fromname(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|level| level.name().eq_ignore_ascii_case(name))
+    }
+    /// Size of the ML-DSA public key
+    const fn mldsapublicbytes(self) -> usize {
+        match self {
+            Signlevel::MlDsa44 => 1312,
+            Signlevel::MlDsa65 => 1952,
+            Signlevel::MlDsa87 => 2592,
+        }
+    }
+    /// Size of the ML-DSA signature
+    const fn mldsasignaturebytes(self) -> usize {
+        match self {
+            Signlevel::MlDsa44 => 2420,
+            Signlevel::MlDsa65 => 3309,
+            Signlevel::MlDsa87 => 4627,
+        }
+    }
+    /// Size of the composite public key
+    pub const fn publickeybytes(self) -> usize {
+        self.mldsapublicbytes() + ED25519PUBLICBYTES
+    }
+    /// Size of the composite signature
+    pub const fn signaturebytes(self) -> usize {
+        self.mldsasignaturebytes() + ED25519SIGNATUREBYTES
+    }
+    /// OID of the ML-DSA component, from NIST
+    #[cfg(feature = "keystore")]
+    fn oid(self) -> ObjectIdentifier {
+        match self {
+            Signlevel::MlDsa44 => ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.3.17"),
+            Signlevel::MlDsa65 => ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.3.18"),
+            Signlevel::MlDsa87 => ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.3.19"),
+        }
+    }
+}
+/// Composite public key, to be published
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Signpub {
+    level: Signlevel,
+    mldsa: Vec<u8>,
+    ed25519: [u8; ED25519PUBLICBYTES],
+}
+/// Composite signature
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Signature {
+    level: Signlevel,
+    signature: Vec<u8>,
+}
+/// Composite signing key, holding the seeds of both secret keys. Keep it safe.
+#[derive(Zeroize, ZeroizeOnDrop)]
+pub struct Signkey {
+    #[zeroize(skip)]
+    public: Signpub,
+    mldsa: [u8; SEEDBYTES],
+    ed25519: [u8; SEEDBYTES],
+}
+impl Signkey {
+    /// Generate a random-secure signing key of the given level
+    #[cfg(feature = "std")]
+    pub fn generate(level: Signlevel) -> Self {
+        Self::generate_with_rng(level, &mut rand::thread_rng())
+    }
+    /// Generate a signing key of the given level from the given random generator
+    pub fn generate_with_rng<R: RngCore + CryptoRng>(level: Signlevel, rng: &mut R) -> Self {
+        let mut mldsa = Zeroizing::new([0u8; SEEDBYTES]);
+        let mut ed25519 = Zeroizing::new([0u8; SEEDBYTES]);
+        rng.fill_bytes(mldsa.as_mut());
+        rng.fill_bytes(ed25519.as_mut());
+        Self::fromsecrets(level, &mldsa, &ed25519)
+    }
+    /// Create a signing key from the seed of the ML-DSA key (`ξ` in FIPS 204) and the Ed25519 secret key
+    pub fn fromsecrets(
+        level: Signlevel,
+        mldsa: &[u8; SEEDBYTES],
+        ed25519: &[u8; SEEDBYTES],
+    ) -> Self {
+        let public = mldsa!(level, P => {
+            let mut seed = B32::from(*mldsa);
+            let keypair = P::key_gen_internal(&seed);
+            seed.zeroize();
+            keypair.verifying_key().encode().to_vec()
+        });
+        Signkey {
+            public: Signpub {
+                level,
+                mldsa: public,
+                ed25519: SigningKey::from_bytes(ed25519).verifying_key().to_bytes(),
+            },
+            mldsa: *mldsa,
+            ed25519: *ed25519,
+        }
+    }
+    /// Level of the key
+    pub fn getlevel(&self) -> Signlevel {
+        self.public.level
+    }
+    /// Public key to give to verifiers
+    pub fn getpublic(&self) -> &Signpub {
+        &self.public
+    }
+    /// Check that both keys have the same public key
+    pub fn checkkeys(&self, other: &Self) -> bool {
+        self.public == other.public
+    }
+    /// Sign a message, ML-DSA being hedged with fresh randomness
+    #[cfg(feature = "std")]
+    pub fn sign(&self, message: &[u8]) -> Result<Signature, PqxError> {
+        self.sign_with_rng(message, &mut rand::thread_rng())
+    }
+    /// Sign a message with the given random generator
+    pub fn sign_with_rng<R: RngCore + CryptoRng>(
+        &self,
+        message: &[u8],
+        rng: &mut R,
+    ) -> Result<Signature, PqxError> {
+        let level = self.getlevel();
+        let mut signature = mldsa!(level, P => {
+            let mut seed = B32::from(self.mldsa);
+            let keypair = P::key_gen_internal(&seed);
+            seed.zeroize();
+            match keypair.signing_key().sign_randomized(message, SIGNLABEL, rng) {
+                Ok(signature) => signature.encode().to_vec(),
+                Err(_) => return Err(PqxError::InvalidInput),
+            }
+        });
+        let ed25519 = SigningKey::from_bytes(&self.ed25519).sign(&labelled(message));
+        signature.extend_from_slice(&ed25519.to_bytes());
+        Ok(Signature { level, signature })
+    }
+}
+impl Signpub {
+    /// Create a public key from the keys of both components
+    pub fn fromparts(level: Signlevel, mldsa: &[u8], ed25519: &[u8]) -> Result<Self, PqxError> {
+        if mldsa.len() != level.mldsapublicbytes() {
+            return Err(PqxError::InvalidLength(mldsa.len()));
+        }
+        let ed25519: [u8; ED25519PUBLICBYTES] = match ed25519.try_into() {
+            Ok(key) => key,
+            Err(_) => return Err(PqxError::InvalidLength(ed25519.len())),
+        };
+        if VerifyingKey::from_bytes(&ed25519).is_err() {
+            return Err(PqxError::InvalidPublicKey);
+        }
+        Ok(Signpub {
+            level,
+            mldsa: mldsa.to_vec(),
+            ed25519,
+        })
+    }
+    /// Encode the public key as the ML-DSA key followed by the Ed25519 key
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.mldsa.clone();
+        bytes.extend_from_slice(&self.ed25519);
+        bytes
+    }
+    /// Decode a public key written by [`Signpub::to_bytes`], the level is given by its size
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PqxError> {
+        match Signlevel::ALL
+            .iter()
+            .find(|level| level.publickeybytes() == bytes.len())
+        {
+            Some(level) => {
+                let (mldsa, ed25519) = bytes.split_at(level.mldsapublicbytes());
+                Self::fromparts(*level, mldsa, ed25519)
+            }
+            None => Err(PqxError::InvalidLength(bytes.len())),
+        }
+    }
+    /// Level of the key
+    pub fn getlevel(&self) -> Signlevel {
+        self.level
+    }
+    /// ML-DSA public key
+    pub fn getmldsa(&self) -> &[u8] {
+        &self.mldsa
+    }
+    /// Ed25519 public key
+    pub fn geted25519(&self) -> &[u8] {
+        &self.ed25519
+    }
+    /// Verify the signature of a message, both components must be valid
+    pub fn verify(&self, message: &[u8], signature: &Signature) -> Result<(), PqxError> {
+        if signature.level != self.level {
+            return Err(PqxError::InvalidSignature);
+        }
+        let (mldsa, ed25519) = signature
+            .signature
+            .split_at(self.level.mldsasignaturebytes());
+        let valid = mldsa!(self.level, P => {
+            let key = match EncodedVerifyingKey::<P>::try_from(self.mldsa.as_slice()) {
+                Ok(key) => ml_dsa::VerifyingKey::<P>::decode(&key),
+                Err(_) => return Err(PqxError::InvalidPublicKey),
+            };
+            match ml_dsa::Signature::<P>::try_from(mldsa) {
+                Ok(mldsa) => key.verify_with_context(message, SIGNLABEL, &mldsa),
+                Err(_) => false,
+            }
+        });
+        let ed25519 = match ed25519.try_into() {
+            Ok(bytes) => ed25519_dalek::Signature::from_bytes(bytes),
+            Err(_) => return Err(PqxError::InvalidSignature),
+        };
+        let key = match VerifyingKey::from_bytes(&self.ed25519) {
+            Ok(key) => key,
+            Err(_) => return Err(PqxError::InvalidPublicKey),
+        };
+        match valid && key.verify_strict(&labelled(message), &ed25519).is_ok() {
+            true => Ok(()),
+            false => Err(PqxError::InvalidSignature),
+        }
+    }
+}
+impl Signature {
+    /// Get the signature to be sent
+    pub fn getbytes(&self) -> &[u8] {
+        &self.signature
+    }
+    /// Level of the signature
+    pub fn getlevel(&self) -> Signlevel {
+        self.level
+    }
+}
+/// Read a signature, the level is given by its size
+impl TryFrom<&[u8]> for Signature {
+    type Error = PqxError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        match Signlevel::ALL
+            .iter()
+            .find(|level| level.signaturebytes() == value.len())
+        {
+            Some(level) => Ok(Signature {
+                level: *level,
+                signature: value.to_vec(),
+            }),
+            None => Err(PqxError::InvalidLength(value.len())),
+        }
+    }
+}
+/// Message signed by Ed25519, prefixed with the label
+fn labelled(message: &[u8]) -> Vec<u8> {
+    let mut labelled = Vec::with_capacity(SIGNLABEL.len() + message.len());
+    labelled.extend_from_slice(SIGNLABEL);
+    labelled.extend_from_slice(message);
+    labelled
+}
+/// Parameters of the composite algorithm: the algorithms of the ML-DSA and Ed25519 components
+#[cfg(feature = "keystore")]
+fn compositeparameters(level: Signlevel) -> Result<Vec<u8>, PqxError> {
+    Ok(alloc::vec![algorithm(level.oid()), algorithm(ED25519OID)].to_der()?)
+}
+/// Level of a composite algorithm, checking it is made of ML-DSA and Ed25519
+#[cfg(feature = "keystore")]
+fn compositelevel(algorithm: &AlgorithmIdentifierRef<'_>) -> Result<Signlevel, PqxError> {
+    let components = match algorithm.parameters {
+        Some(parameters) if algorithm.oid == COMPOSITEOID => {
+            parameters.decode_as::<Vec<AlgorithmIdentifierRef<'_>>>()?
+        }
+        _ => return Err(PqxError::InvalidInput),
+    };
+    match components.as_slice() {
+        [mldsa, ed25519]
+            if mldsa.parameters.is_none()
+                && ed25519.oid == ED25519OID
+                && ed25519.parameters.is_none() =>
+        {
+            match Signlevel::ALL.iter().find(|level| level.oid() == mldsa.oid) {
+                Some(level) => Ok(*level),
+                None => Err(PqxError::InvalidInput),
+            }
+        }
+        _ => Err(PqxError::InvalidInput),
+    }
+}
+/// Encode the public key as a DER SubjectPublicKeyInfo of the composite ML-DSA and Ed25519 algorithm.
+/// The public key is a sequence of the bit strings of both keys.
+#[cfg(feature = "keystore")]
+pub fn signpublictoder(key: &Signpub) -> Result<Vec<u8>, PqxError> {
+    let parameters = compositeparameters(key.level)?;
+    let components = alloc::vec![
+        BitStringRef::from_bytes(&key.mldsa)?,
+        BitStringRef::from_bytes(&key.ed25519)?,
+    ]
+    .to_der()?;
+    let info = SubjectPublicKeyInfoRef {
+        algorithm: compositealgorithm(&parameters)?,
+        subject_public_key: BitStringRef::from_bytes(&components)?,
+    };
+    Ok(info.to_der()?)
+}
+/// Decode a public key written by [`signpublictoder`]
+#[cfg(feature = "keystore")]
+pub fn signpublicfromder(der: &[u8]) -> Result<Signpub, PqxError> {
+    let info = SubjectPublicKeyInfoRef::from_der(der)?;
+    let level = compositelevel(&info.algorithm)?;
+    let components = match info.subject_public_key.as_bytes() {
+        Some(components) => Vec::<BitStringRef<'_>>::from_der(components)?,
+        None => return Err(PqxError::InvalidInput),
+    };
+    match components.as_slice() {
+        [mldsa, ed25519] => match (mldsa.as_bytes(), ed25519.as_bytes()) {
+            (Some(mldsa), Some(ed25519)) => Signpub::fromparts(level, mldsa, ed25519),
+            _ => Err(PqxError::InvalidInput),
+        },
+        _ => Err(PqxError::InvalidInput),
+    }
+}
+/// Encode the private key as a DER PKCS#8 OneAsymmetricKey of the composite algorithm, made of the sequence of the keys
+/// of both components. The ML-DSA key is its seed (`[0] IMPLICIT OCTET STRING`) and the Ed25519 one is from RFC 8410.
+#[cfg(feature = "keystore")]
+pub fn signprivatetoder(key: &Signkey) -> Result<Zeroizing<Vec<u8>>, PqxError> {
+    let mut mldsa = Zeroizing::new(Vec::with_capacity(SEEDBYTES + 2));
+    mldsa.extend_from_slice(&[0x80, SEEDBYTES as u8]);
+    mldsa.extend_from_slice(&key.mldsa);
+    let ed25519 = Zeroizing::new(OctetStringRef::new(&key.ed25519)?.to_der()?);
+    let components = Zeroizing::new(
+        alloc::vec![
+            PrivateKeyInfo::new(algorithm(key.getlevel().oid()), &mldsa),
+            PrivateKeyInfo::new(algorithm(ED25519OID), &ed25519),
+        ]
+        .to_der()?,
+    );
+    let parameters = compositeparameters(key.getlevel())?;
+    let info = PrivateKeyInfo::new(compositealgorithm(&parameters)?, &components);
+    Ok(Zeroizing::new(info.to_der()?))
+}
+/// Decode a private key written by [`signprivatetoder`]
+#[cfg(feature = "keystore")]
+pub fn signprivatefromder(der: &[u8]) -> Result<Signkey, PqxError> {
+    let info = PrivateKeyInfo::from_der(der)?;
+    let level = compositelevel(&info.algorithm)?;
+    let components = Vec::<PrivateKeyInfo<'_>>::from_der(info.private_key)?;
+    let (mldsa, ed25519) = match components.as_slice() {
+        [mldsa, ed25519]
+            if mldsa.algorithm == algorithm(level.oid())
+                && ed25519.algorithm == algorithm(ED25519OID) =>
+        {
+            (mldsa, ed25519)
+        }
+        _ => return Err(PqxError::InvalidInput),
+    };
+    let mldsa: &[u8; SEEDBYTES] = match mldsa.private_key {
+        [0x80, len, seed @ ..] if *len as usize == SEEDBYTES => match seed.try_into() {
+            Ok(seed) => seed,
+            Err(_) => return Err(PqxError::InvalidLength(seed.len())),
+        },
+        _ => return Err(PqxError::InvalidInput),
+    };
+    let ed25519 = OctetStringRef::from_der(ed25519.private_key)?;
+    let ed25519: &[u8; SEEDBYTES] = match ed25519.as_bytes().try_into() {
+        Ok(secret) => secret,
+        Err(_) => return Err(PqxError::InvalidLength(ed25519.as_bytes().len())),
+    };
+    Ok(Signkey::fromsecrets(level, mldsa, ed25519))
+}
+/// Encode the signing key as PEM, returns the private then the public key
+#[cfg(feature = "keystore")]
+pub fn signkeystostr(key: &Signkey) -> Result<(Zeroizing<String>, String), PqxError> {
+    let document = SecretDocument::try_from(signprivatetoder(key)?.as_slice())?;
+    let private = document.to_pem(PRIVATEPEM, PEM_LINE_ENDING)?;
+    let document = Document::try_from(signpublictoder(key.getpublic())?)?;
+    let public = document.to_pem(PUBLICPEM, PEM_LINE_ENDING)?;
+    Ok((private, public))
+}
+/// Decode a PEM public key, as given to verifiers
+#[cfg(feature = "keystore")]
+pub fn signpublicfromstr(public: &str) -> Result<Signpub, PqxError> {
+    signpublicfromder(frompem(public, PUBLICPEM)?.as_bytes())
+}
+/// Decode both PEM keys, checking that they match
+#[cfg(feature = "keystore")]
+pub fn signkeysfromstr(public: &str, private: &str) -> Result<Signkey, PqxError> {
+    let key = signprivatefromder(frompem(private, PRIVATEPEM)?.as_bytes())?;
+    match *key.getpublic() == signpublicfromstr(public)? {
+        true => Ok(key),
+        false => Err(PqxError::KeyMismatch),
+    }
+}
+/// Print the signing key to files. Keep your private key safe.
+/// Files are written atomically and existing files are only replaced if overwrite is set.
+/// ```rust
+/// use pqx::sign::*;
+/// let key = Signkey::generate(Signlevel::MlDsa44);
+/// let directory = tempfile::tempdir().unwrap();
+/// let privatepath = directory.path().join("sign.key");
+/// let publicpath = directory.path().join("sign.pub");
+/// printsignkeystofile(&key, &privatepath, &publicpath, false).unwrap();
+/// let mut privatefile = std::fs::File::open(&privatepath).unwrap();
+/// let mut publicfile = std::fs::File::open(&publicpath).unwrap();
+/// let testkey = extractsignkeysfromfile(&mut publicfile, &mut privatefile).unwrap();
+/// assert!(testkey.checkkeys(&key));
+/// ```
+#[cfg(feature = "keystore")]
+pub fn printsignkeystofile<T>(
+    key: &Signkey,
+    privatekey: T,
+    publickey: T,
+    overwrite: bool,
+) -> Result<(), PqxError>
+where
+    T: AsRef<OsStr>,
+{
+    let (private, public) = signkeystostr(key)?;
+    writekeys(
+        Path::new(privatekey.as_ref()),
+        &private,
+        Path::new(publickey.as_ref()),
+        &public,
+        overwrite,
+    )
+}
+/// Write the signing key to any writer, as [`printsignkeystofile`] does to files
+#[cfg(feature = "keystore")]
+pub fn printsignkeys<S: Write, P: Write>(
+    key: &Signkey,
+    private: S,
+    public: P,
+) -> Result<(), PqxError> {
+    let (privatetext, publictext) = signkeystostr(key)?;
+    printtexts(private, &privatetext, public, &publictext)
+}
+/// Extract the signing key from files written by [`printsignkeystofile`]
+#[cfg(feature = "keystore")]
+pub fn extractsignkeysfromfile(public: &mut File, private: &mut File) -> Result<Signkey, PqxError> {
+    extractsignkeys(public, private)
+}
+/// Extract the signing key from any reader
+#[cfg(feature = "keystore")]
+pub fn extractsignkeys<P: Read, S: Read>(public: P, private: S) -> Result<Signkey, PqxError> {
+    signkeysfromstr(&readtext(public)?, &readtext(private)?)
+}